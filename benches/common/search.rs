@@ -5,6 +5,7 @@ use std::{
 };
 
 use grep_rs::{
+    color::ColorSpecs,
     search_runner::{run_search_to_writer, search_line_by_line, search_text_content, SearchConfig},
     CompiledRegex,
 };
@@ -22,36 +23,38 @@ pub fn read_fixture(relative: &str) -> String {
         .unwrap_or_else(|err| panic!("failed to read {}: {err}", path.display()))
 }
 
-fn default_search_config(threads: usize) -> SearchConfig {
+fn default_search_config(threads: usize) -> SearchConfig<'static> {
     SearchConfig {
         only_matching: false,
         use_color: false,
-        show_prefix: false,
+        colors: ColorSpecs::default(),
+        show_prefix: None,
+        null_after_name: false,
+        line_terminator: b'\n',
+        multiline: false,
+        replacement: None,
+        rewrite: None,
+        preprocessor: None,
+        search_zip: false,
+        search_archives: false,
+        max_archive_depth: 0,
         threads,
     }
 }
 
 pub fn bench_search_text_with_compiled(compiled: &CompiledRegex, input: &str) {
     let mut output = Vec::new();
-    black_box(search_text_content(
-        input,
-        compiled,
-        &default_search_config(1),
-        "",
-        &mut output,
-    ));
+    black_box(
+        search_text_content(input, compiled, &default_search_config(1), b"", &mut output).unwrap(),
+    );
     black_box(output.len());
 }
 
 pub fn bench_search_text_line_by_line_with_compiled(compiled: &CompiledRegex, input: &str) {
     let mut output = Vec::new();
-    black_box(search_line_by_line(
-        input,
-        compiled,
-        &default_search_config(1),
-        "",
-        &mut output,
-    ));
+    black_box(
+        search_line_by_line(input, compiled, &default_search_config(1), b"", &mut output).unwrap(),
+    );
     black_box(output.len());
 }
 
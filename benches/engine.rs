@@ -50,6 +50,31 @@ fn find_all_benches(c: &mut Criterion) {
             r"^((\w+)-(\d+)) and \1$",
             "token123-123456 and token123-123456",
         ),
+        (
+            "backref_segments",
+            r"(\d+)x(\w+)y\2\1",
+            "id 4821 ref 4821xorder_99yorder_994821 done",
+        ),
+        (
+            "vm_word_loop",
+            r"(\w+) (?:\w+ )*\1$",
+            "alpha beta gamma delta epsilon zeta eta theta iota kappa alpha",
+        ),
+        (
+            "vm_literal_run",
+            r"(?=\w)(\w+)-ident-(\d+) and \1-ident-\2",
+            "token-ident-123456 and token-ident-123456",
+        ),
+        (
+            "vm_wildcard_loop",
+            r"(\d+).*\1$",
+            "order 12345 shipped to warehouse 98765 under ref 12345",
+        ),
+        (
+            "vm_class_loop",
+            r"([a-z]+)[ ,;]+\1\b",
+            "the quick brown fox, jumps over the lazy dog; dog",
+        ),
     ] {
         let compiled = grep_rs::compile_regex(pattern);
         group.bench_function(name, |b| {
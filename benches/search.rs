@@ -10,6 +10,9 @@ fn search_pipeline_benches(c: &mut Criterion) {
     let literal_compiled = grep_rs::compile_regex("matched_line_[0123456789]+");
     let alternation_compiled =
         grep_rs::compile_regex("message=(matched_line|ordinary_line)_[0123456789]+");
+    let inner_literal_compiled = grep_rs::compile_regex("[0-9]+ level=INFO .*");
+    let backref_text = common::read_fixture("backref.txt");
+    let backref_compiled = grep_rs::compile_regex(r"(\w+)-(\d+) and \1-\2");
 
     let mut group = c.benchmark_group("search_pipeline");
     group.bench_function("serial_file_search_literal_prefix", |b| {
@@ -50,6 +53,25 @@ fn search_pipeline_benches(c: &mut Criterion) {
             common::bench_search_text_line_by_line_with_compiled(&alternation_compiled, &data_text)
         })
     });
+    group.bench_function("candidate_line_inner_literal", |b| {
+        b.iter(|| common::bench_search_text_with_compiled(&inner_literal_compiled, &data_text))
+    });
+    group.bench_function("line_by_line_inner_literal", |b| {
+        b.iter(|| {
+            common::bench_search_text_line_by_line_with_compiled(
+                &inner_literal_compiled,
+                &data_text,
+            )
+        })
+    });
+    group.bench_function("candidate_line_backref_multiple", |b| {
+        b.iter(|| common::bench_search_text_with_compiled(&backref_compiled, &backref_text))
+    });
+    group.bench_function("line_by_line_backref_multiple", |b| {
+        b.iter(|| {
+            common::bench_search_text_line_by_line_with_compiled(&backref_compiled, &backref_text)
+        })
+    });
     group.finish();
 }
 
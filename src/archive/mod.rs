@@ -0,0 +1,455 @@
+mod tar;
+mod zip;
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Read},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{Context, Result};
+
+use crate::decompress::{
+    decompress, inflate_raw, CompressionFormat, DecompressedTooLarge, MAX_DECOMPRESSED_LEN,
+};
+
+/// Enough leading bytes to see both the zip signature and the tar `ustar`
+/// magic at offset 257.
+const SNIFF_LEN: usize = 512;
+
+const TARBALL_SUFFIXES: [&str; 8] = [
+    ".tar.gz", ".tgz", ".tar.bz2", ".tbz2", ".tar.xz", ".txz", ".tar.zst", ".tzst",
+];
+
+/// A file inside an archive, labelled `archive.tar!inner/path.txt`. Nested
+/// archives add one `!` per level.
+#[derive(Debug, Clone)]
+pub(crate) struct ArchiveMember {
+    pub(crate) label: PathBuf,
+    pub(crate) data: MemberData,
+}
+
+/// Member bytes as a range of a shared buffer, so tar members borrow from the
+/// archive they were read from instead of being copied.
+#[derive(Clone)]
+pub(crate) struct MemberData {
+    bytes: Arc<[u8]>,
+    range: Range<usize>,
+}
+
+impl MemberData {
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.bytes[self.range.clone()]
+    }
+}
+
+impl fmt::Debug for MemberData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemberData")
+            .field("len", &self.range.len())
+            .finish()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ArchiveFormat {
+    Tar,
+    Zip,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ArchiveKind {
+    Plain(ArchiveFormat),
+    CompressedTar(CompressionFormat),
+}
+
+/// An archive entry as listed by a format reader, before it is materialized.
+struct Entry {
+    name: String,
+    data: EntryData,
+}
+
+enum EntryData {
+    Stored(Range<usize>),
+    Deflated {
+        range: Range<usize>,
+        size: usize,
+        crc: u32,
+    },
+}
+
+/// Where archive reading is allowed to go.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ArchiveLimits {
+    /// How many archive levels to open. `1` searches the members of top-level
+    /// archives only; nested archives beyond the limit are searched as plain
+    /// members.
+    pub(crate) max_depth: usize,
+    /// Whether compressed tarballs (`.tar.gz`, `.tgz`, ...) are opened.
+    pub(crate) search_zip: bool,
+}
+
+/// An archive whose members could not be read. The search reports it and
+/// moves on to the next file.
+#[derive(Debug)]
+pub(crate) struct UnreadableArchive(PathBuf);
+
+impl fmt::Display for UnreadableArchive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.display())
+    }
+}
+
+/// An archive whose members decompress to more than the limit in total.
+/// Members are held in memory until they are searched, so the limit covers
+/// the whole archive rather than each member.
+#[derive(Debug)]
+pub(crate) struct ArchiveTooLarge {
+    limit: usize,
+}
+
+impl fmt::Display for ArchiveTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "archive members decompress to more than {} bytes",
+            self.limit
+        )
+    }
+}
+
+impl std::error::Error for ArchiveTooLarge {}
+
+/// Decompressed bytes an archive has left to expand into.
+struct Budget {
+    limit: usize,
+    remaining: usize,
+}
+
+impl Budget {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            remaining: limit,
+        }
+    }
+
+    /// Runs `decompress` with the bytes left as its size limit, and charges
+    /// its output to the budget.
+    fn spend(&mut self, decompress: impl FnOnce(usize) -> Result<Vec<u8>>) -> Result<Vec<u8>> {
+        let data = decompress(self.remaining).map_err(|err| {
+            if err.is::<DecompressedTooLarge>() {
+                ArchiveTooLarge { limit: self.limit }.into()
+            } else {
+                err
+            }
+        })?;
+        self.remaining -= data.len();
+        Ok(data)
+    }
+}
+
+/// Whether `path` is a tar or zip archive that `read_archive` opens.
+/// Compressed tarballs are only recognized by name, so ordinary compressed
+/// files are not decompressed twice. Files that cannot be read are not
+/// archives; searching them reports the error.
+pub(crate) fn is_archive(path: &Path, limits: ArchiveLimits) -> bool {
+    limits.max_depth > 0
+        && File::open(path)
+            .and_then(|mut file| read_header(&mut file))
+            .is_ok_and(|header| sniff(&header, &path.to_string_lossy(), limits).is_some())
+}
+
+/// Lists the members of the archive at `path`. Any failure is reported as
+/// an `UnreadableArchive`.
+pub(crate) fn read_archive(path: &Path, limits: ArchiveLimits) -> Result<Vec<ArchiveMember>> {
+    read_members(path, limits).map_err(|err| err.context(UnreadableArchive(path.to_path_buf())))
+}
+
+fn read_members(path: &Path, limits: ArchiveLimits) -> Result<Vec<ArchiveMember>> {
+    let mut file = File::open(path)?;
+    let mut bytes = read_header(&mut file)?;
+    let kind =
+        sniff(&bytes, &path.to_string_lossy(), limits).context("not a tar or zip archive")?;
+    file.read_to_end(&mut bytes)?;
+    let mut members = Vec::new();
+    expand(
+        path,
+        bytes.into(),
+        kind,
+        limits,
+        limits.max_depth,
+        &mut Budget::new(MAX_DECOMPRESSED_LEN),
+        &mut members,
+    )?;
+    Ok(members)
+}
+
+fn read_header(file: &mut File) -> io::Result<Vec<u8>> {
+    let mut header = Vec::with_capacity(SNIFF_LEN);
+    file.take(SNIFF_LEN as u64).read_to_end(&mut header)?;
+    Ok(header)
+}
+
+fn sniff(header: &[u8], name: &str, limits: ArchiveLimits) -> Option<ArchiveKind> {
+    if zip::is_zip(header) {
+        Some(ArchiveKind::Plain(ArchiveFormat::Zip))
+    } else if tar::is_tar(header) {
+        Some(ArchiveKind::Plain(ArchiveFormat::Tar))
+    } else if limits.search_zip && TARBALL_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)) {
+        CompressionFormat::detect(header).map(ArchiveKind::CompressedTar)
+    } else {
+        None
+    }
+}
+
+fn expand(
+    label: &Path,
+    bytes: Arc<[u8]>,
+    kind: ArchiveKind,
+    limits: ArchiveLimits,
+    depth_left: usize,
+    budget: &mut Budget,
+    members: &mut Vec<ArchiveMember>,
+) -> Result<()> {
+    let (bytes, format) = match kind {
+        ArchiveKind::Plain(format) => (bytes, format),
+        ArchiveKind::CompressedTar(compression) => (
+            budget
+                .spend(|limit| decompress(compression, &bytes, limit))?
+                .into(),
+            ArchiveFormat::Tar,
+        ),
+    };
+    let entries = match format {
+        ArchiveFormat::Tar => tar::entries(&bytes)?,
+        ArchiveFormat::Zip => zip::entries(&bytes)?,
+    };
+
+    for entry in entries {
+        let member_label = PathBuf::from(format!("{}!{}", label.display(), entry.name));
+        let data = match entry.data {
+            EntryData::Stored(range) => MemberData {
+                bytes: Arc::clone(&bytes),
+                range,
+            },
+            EntryData::Deflated { range, size, crc } => {
+                let inflated = budget.spend(|limit| {
+                    inflate_raw(&bytes[range], size, limit).with_context(|| {
+                        format!("{}: invalid deflate data", member_label.display())
+                    })
+                })?;
+                zip::check_crc(&inflated, crc)
+                    .with_context(|| member_label.display().to_string())?;
+                MemberData {
+                    range: 0..inflated.len(),
+                    bytes: inflated.into(),
+                }
+            }
+        };
+
+        let nested = (depth_left > 1)
+            .then(|| sniff(data.as_bytes(), &entry.name, limits))
+            .flatten();
+        match nested {
+            Some(kind) => expand(
+                &member_label,
+                data.as_bytes().into(),
+                kind,
+                limits,
+                depth_left - 1,
+                budget,
+                members,
+            )?,
+            None => members.push(ArchiveMember {
+                label: member_label,
+                data,
+            }),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod fixtures {
+    /// Builds a ustar archive holding `files` as regular members.
+    pub(crate) fn build_tar(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        for (name, contents) in files {
+            let mut header = [0u8; 512];
+            header[..name.len()].copy_from_slice(name.as_bytes());
+            header[100..107].copy_from_slice(b"0000644");
+            header[124..135].copy_from_slice(format!("{:011o}", contents.len()).as_bytes());
+            header[156] = b'0';
+            header[257..263].copy_from_slice(b"ustar\0");
+            header[263..265].copy_from_slice(b"00");
+            header[148..156].fill(b' ');
+            let checksum = header.iter().map(|byte| u32::from(*byte)).sum::<u32>();
+            header[148..155].copy_from_slice(format!("{checksum:06o}\0").as_bytes());
+
+            archive.extend_from_slice(&header);
+            archive.extend_from_slice(contents);
+            archive.resize(archive.len().next_multiple_of(512), 0);
+        }
+        archive.resize(archive.len() + 1024, 0);
+        archive
+    }
+
+    /// Builds a zip archive holding `files` uncompressed.
+    pub(crate) fn build_zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        let mut central = Vec::new();
+        for (name, contents) in files {
+            let offset = archive.len() as u32;
+            let crc = crate::decompress::crc32(contents);
+            let mut fields = Vec::new();
+            fields.extend_from_slice(&crc.to_le_bytes());
+            fields.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            fields.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            fields.extend_from_slice(&0u16.to_le_bytes());
+
+            archive.extend_from_slice(&[b'P', b'K', 3, 4, 20, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            archive.extend_from_slice(&fields);
+            archive.extend_from_slice(name.as_bytes());
+            archive.extend_from_slice(contents);
+
+            central.extend_from_slice(&[b'P', b'K', 1, 2, 20, 0, 20, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            central.extend_from_slice(&fields);
+            central.extend_from_slice(&[0; 6]);
+            central.extend_from_slice(&0u32.to_le_bytes());
+            central.extend_from_slice(&offset.to_le_bytes());
+            central.extend_from_slice(name.as_bytes());
+        }
+
+        let central_offset = archive.len() as u32;
+        archive.extend_from_slice(&central);
+        archive.extend_from_slice(&[b'P', b'K', 5, 6, 0, 0, 0, 0]);
+        archive.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        archive.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        archive.extend_from_slice(&(central.len() as u32).to_le_bytes());
+        archive.extend_from_slice(&central_offset.to_le_bytes());
+        archive.extend_from_slice(&0u16.to_le_bytes());
+        archive
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fixtures::*, *};
+
+    fn expand_with_budget(
+        bytes: Vec<u8>,
+        max_depth: usize,
+        budget: usize,
+    ) -> Result<Vec<ArchiveMember>> {
+        let limits = ArchiveLimits {
+            max_depth,
+            search_zip: false,
+        };
+        let kind = sniff(&bytes, "", limits).expect("archive is recognized");
+        let mut members = Vec::new();
+        expand(
+            Path::new("a"),
+            bytes.into(),
+            kind,
+            limits,
+            max_depth,
+            &mut Budget::new(budget),
+            &mut members,
+        )?;
+        Ok(members)
+    }
+
+    fn labels_and_text(bytes: Vec<u8>, max_depth: usize) -> Vec<(String, String)> {
+        expand_with_budget(bytes, max_depth, MAX_DECOMPRESSED_LEN)
+            .unwrap()
+            .into_iter()
+            .map(|member| {
+                (
+                    member.label.display().to_string(),
+                    String::from_utf8_lossy(member.data.as_bytes()).into_owned(),
+                )
+            })
+            .collect()
+    }
+
+    /// A zip holding `log.txt` as `first line\nsecond line\n`, deflated.
+    fn build_deflated_zip() -> Vec<u8> {
+        // `printf 'first line\nsecond line\n'` as a raw DEFLATE stream.
+        let deflated = [
+            0x4b, 0xcb, 0x2c, 0x2a, 0x2e, 0x51, 0xc8, 0xc9, 0xcc, 0x4b, 0xe5, 0x2a, 0x4e, 0x4d,
+            0xce, 0xcf, 0x4b, 0x81, 0xb0, 0x01,
+        ];
+        let text = b"first line\nsecond line\n";
+        let mut zip = build_zip(&[("log.txt", &deflated)]);
+        // Patch method, CRC and uncompressed size in both headers.
+        let crc = crate::decompress::crc32(text).to_le_bytes();
+        let size = (text.len() as u32).to_le_bytes();
+        let central = zip.len() - 22 - (46 + "log.txt".len());
+        for (method, fields) in [(8, 14), (central + 10, central + 16)] {
+            zip[method] = 8;
+            zip[fields..fields + 4].copy_from_slice(&crc);
+            zip[fields + 8..fields + 12].copy_from_slice(&size);
+        }
+        zip
+    }
+
+    #[test]
+    fn lists_tar_and_zip_members_in_archive_order() {
+        let files: [(&str, &[u8]); 2] = [("docs/readme.txt", b"hello\n"), ("empty", b"")];
+        let expected = vec![
+            ("a!docs/readme.txt".to_string(), "hello\n".to_string()),
+            ("a!empty".to_string(), String::new()),
+        ];
+        assert_eq!(labels_and_text(build_tar(&files), 1), expected);
+        assert_eq!(labels_and_text(build_zip(&files), 1), expected);
+    }
+
+    #[test]
+    fn nested_archives_stop_at_the_depth_limit() {
+        let inner = build_zip(&[("deep.txt", b"needle\n")]);
+        let outer = build_tar(&[("inner.zip", &inner), ("top.txt", b"top\n")]);
+
+        let opened = labels_and_text(outer.clone(), 2);
+        assert_eq!(
+            opened,
+            vec![
+                ("a!inner.zip!deep.txt".to_string(), "needle\n".to_string()),
+                ("a!top.txt".to_string(), "top\n".to_string()),
+            ]
+        );
+
+        let capped = labels_and_text(outer, 1);
+        assert_eq!(capped[0].0, "a!inner.zip");
+    }
+
+    #[test]
+    fn deflated_zip_members_are_inflated() {
+        assert_eq!(
+            labels_and_text(build_deflated_zip(), 1),
+            vec![(
+                "a!log.txt".to_string(),
+                "first line\nsecond line\n".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn members_share_one_decompression_budget() {
+        let zip = build_deflated_zip();
+        let nested = build_tar(&[("one.zip", &zip), ("two.zip", &zip)]);
+
+        // `log.txt` inflates to 23 bytes, so two copies fit in 46.
+        assert_eq!(expand_with_budget(nested.clone(), 2, 46).unwrap().len(), 2);
+        let err = expand_with_budget(nested, 2, 45).unwrap_err();
+        assert!(err.is::<ArchiveTooLarge>(), "{err:#}");
+        assert_eq!(
+            err.to_string(),
+            "archive members decompress to more than 45 bytes"
+        );
+    }
+}
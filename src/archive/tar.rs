@@ -0,0 +1,139 @@
+use anyhow::{bail, ensure, Result};
+
+use super::{Entry, EntryData};
+
+const BLOCK_LEN: usize = 512;
+const MAGIC_OFFSET: usize = 257;
+
+pub(super) fn is_tar(header: &[u8]) -> bool {
+    header
+        .get(MAGIC_OFFSET..MAGIC_OFFSET + 5)
+        .is_some_and(|magic| magic == b"ustar")
+}
+
+/// Lists the regular files of a ustar, GNU or pax archive. Long names from
+/// GNU `L` records and pax `path` records replace the header name.
+pub(super) fn entries(archive: &[u8]) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut long_name = None;
+    let mut offset = 0;
+
+    while offset + BLOCK_LEN <= archive.len() {
+        let header = &archive[offset..offset + BLOCK_LEN];
+        if header.iter().all(|byte| *byte == 0) {
+            break;
+        }
+        ensure!(
+            checksum_matches(header),
+            "corrupt tar header at byte {offset}"
+        );
+
+        let size = parse_number(&header[124..136])?;
+        let data_start = offset + BLOCK_LEN;
+        let data_end = data_start
+            .checked_add(size)
+            .filter(|end| *end <= archive.len())
+            .ok_or_else(|| anyhow::anyhow!("truncated tar member at byte {offset}"))?;
+        let data = &archive[data_start..data_end];
+
+        match header[156] {
+            b'L' => long_name = Some(field(data).to_string()),
+            b'x' => long_name = pax_path(data).or(long_name),
+            b'0' | b'\0' | b'7' => entries.push(Entry {
+                name: long_name.take().unwrap_or_else(|| header_name(header)),
+                data: EntryData::Stored(data_start..data_end),
+            }),
+            _ => long_name = None,
+        }
+
+        offset = data_start + size.next_multiple_of(BLOCK_LEN);
+    }
+
+    Ok(entries)
+}
+
+fn header_name(header: &[u8]) -> String {
+    let name = field(&header[..100]);
+    let prefix = if is_tar(header) {
+        field(&header[345..500])
+    } else {
+        "".into()
+    };
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{prefix}/{name}")
+    }
+}
+
+/// A NUL-terminated header field. Non-UTF-8 names are replaced lossily since
+/// they are only used for display.
+fn field(bytes: &[u8]) -> std::borrow::Cow<'_, str> {
+    let len = bytes
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len])
+}
+
+/// Parses an octal size field, or the GNU base-256 form used for members
+/// larger than 8 GiB.
+fn parse_number(bytes: &[u8]) -> Result<usize> {
+    if bytes[0] & 0x80 != 0 {
+        let mut value = u64::from(bytes[0] & 0x7f);
+        for byte in &bytes[1..] {
+            value = value
+                .checked_mul(256)
+                .ok_or_else(|| anyhow::anyhow!("tar size overflows"))?
+                + u64::from(*byte);
+        }
+        return Ok(usize::try_from(value)?);
+    }
+
+    let text = field(bytes);
+    let text = text.trim_matches(|ch: char| ch == ' ' || ch == '\0');
+    if text.is_empty() {
+        return Ok(0);
+    }
+    match usize::from_str_radix(text, 8) {
+        Ok(value) => Ok(value),
+        Err(_) => bail!("invalid tar number {text:?}"),
+    }
+}
+
+fn checksum_matches(header: &[u8]) -> bool {
+    let Ok(expected) = parse_number(&header[148..156]) else {
+        return false;
+    };
+    let actual = header
+        .iter()
+        .enumerate()
+        .map(|(index, byte)| {
+            if (148..156).contains(&index) {
+                usize::from(b' ')
+            } else {
+                usize::from(*byte)
+            }
+        })
+        .sum::<usize>();
+    actual == expected
+}
+
+/// Extracts `path` from pax extended header records (`"<len> key=value\n"`).
+fn pax_path(data: &[u8]) -> Option<String> {
+    let mut rest = data;
+    while !rest.is_empty() {
+        let space = rest.iter().position(|byte| *byte == b' ')?;
+        let len = std::str::from_utf8(&rest[..space])
+            .ok()?
+            .parse::<usize>()
+            .ok()?;
+        let record = rest.get(space + 1..len)?;
+        let record = record.strip_suffix(b"\n").unwrap_or(record);
+        if let Some(path) = record.strip_prefix(b"path=") {
+            return Some(String::from_utf8_lossy(path).into_owned());
+        }
+        rest = &rest[len..];
+    }
+    None
+}
@@ -0,0 +1,116 @@
+use anyhow::{ensure, Result};
+
+use super::{Entry, EntryData};
+use crate::decompress::crc32;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+const END_OF_CENTRAL_DIRECTORY_LEN: usize = 22;
+const LOCAL_HEADER_LEN: usize = 30;
+const CENTRAL_HEADER_LEN: usize = 46;
+const MAX_COMMENT_LEN: usize = u16::MAX as usize;
+
+const FLAG_ENCRYPTED: u16 = 0x0001;
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+pub(super) fn is_zip(header: &[u8]) -> bool {
+    header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06")
+}
+
+/// Lists the files in a zip archive from its central directory. Encrypted
+/// entries and compression methods other than stored and deflate are skipped.
+pub(super) fn entries(archive: &[u8]) -> Result<Vec<Entry>> {
+    let end = find_end_of_central_directory(archive)?;
+    let entry_count = usize::from(read_u16(archive, end + 10)?);
+    let directory_offset = read_u32(archive, end + 16)?;
+    ensure!(
+        entry_count != usize::from(u16::MAX) && directory_offset != u32::MAX,
+        "zip64 archives are not supported"
+    );
+
+    let mut entries = Vec::with_capacity(entry_count);
+    let mut offset = directory_offset as usize;
+    for _ in 0..entry_count {
+        ensure!(
+            read_u32(archive, offset)? == CENTRAL_HEADER_SIGNATURE,
+            "corrupt zip central directory"
+        );
+        let flags = read_u16(archive, offset + 8)?;
+        let method = read_u16(archive, offset + 10)?;
+        let crc = read_u32(archive, offset + 16)?;
+        let compressed_size = read_u32(archive, offset + 20)? as usize;
+        let size = read_u32(archive, offset + 24)? as usize;
+        let name_len = usize::from(read_u16(archive, offset + 28)?);
+        let extra_len = usize::from(read_u16(archive, offset + 30)?);
+        let comment_len = usize::from(read_u16(archive, offset + 32)?);
+        let local_offset = read_u32(archive, offset + 42)? as usize;
+        let name = slice(archive, offset + CENTRAL_HEADER_LEN, name_len)?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        offset += CENTRAL_HEADER_LEN + name_len + extra_len + comment_len;
+
+        if name.ends_with('/') || flags & FLAG_ENCRYPTED != 0 {
+            continue;
+        }
+
+        ensure!(
+            read_u32(archive, local_offset)? == LOCAL_HEADER_SIGNATURE,
+            "corrupt zip local header for {name}"
+        );
+        let data_start = local_offset
+            + LOCAL_HEADER_LEN
+            + usize::from(read_u16(archive, local_offset + 26)?)
+            + usize::from(read_u16(archive, local_offset + 28)?);
+        slice(archive, data_start, compressed_size)?;
+        let range = data_start..data_start + compressed_size;
+
+        let data = match method {
+            METHOD_STORED => {
+                check_crc(&archive[range.clone()], crc)?;
+                EntryData::Stored(range)
+            }
+            METHOD_DEFLATED => EntryData::Deflated { range, size, crc },
+            _ => continue,
+        };
+        entries.push(Entry { name, data });
+    }
+
+    Ok(entries)
+}
+
+pub(super) fn check_crc(data: &[u8], expected: u32) -> Result<()> {
+    ensure!(crc32(data) == expected, "zip CRC mismatch");
+    Ok(())
+}
+
+/// Scans backwards for the end of central directory record, which may be
+/// followed by an archive comment.
+fn find_end_of_central_directory(archive: &[u8]) -> Result<usize> {
+    let last = archive
+        .len()
+        .checked_sub(END_OF_CENTRAL_DIRECTORY_LEN)
+        .ok_or_else(|| anyhow::anyhow!("truncated zip archive"))?;
+    let first = last.saturating_sub(MAX_COMMENT_LEN);
+    (first..=last)
+        .rev()
+        .find(|offset| read_u32(archive, *offset).ok() == Some(END_OF_CENTRAL_DIRECTORY_SIGNATURE))
+        .ok_or_else(|| anyhow::anyhow!("missing zip central directory"))
+}
+
+fn slice(archive: &[u8], offset: usize, len: usize) -> Result<&[u8]> {
+    offset
+        .checked_add(len)
+        .and_then(|end| archive.get(offset..end))
+        .ok_or_else(|| anyhow::anyhow!("truncated zip archive"))
+}
+
+fn read_u16(archive: &[u8], offset: usize) -> Result<u16> {
+    let bytes = slice(archive, offset, 2)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(archive: &[u8], offset: usize) -> Result<u32> {
+    let bytes = slice(archive, offset, 4)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
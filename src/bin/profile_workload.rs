@@ -3,6 +3,7 @@ use std::{hint::black_box, io, path::PathBuf};
 use anyhow::{bail, Result};
 use clap::{Parser, ValueEnum};
 use grep_rs::{
+    color::ColorSpecs,
     compile_regex,
     search_runner::{run_search_to_writer, search_text_content, SearchConfig},
 };
@@ -79,14 +80,24 @@ fn run_text_workload(fixture_name: &str, pattern: &str, iters: usize) -> Result<
     let config = SearchConfig {
         only_matching: false,
         use_color: false,
-        show_prefix: false,
+        colors: ColorSpecs::default(),
+        show_prefix: None,
+        null_after_name: false,
+        line_terminator: b'\n',
+        multiline: false,
+        replacement: None,
+        rewrite: None,
+        preprocessor: None,
+        search_zip: false,
+        search_archives: false,
+        max_archive_depth: 0,
         threads: 1,
     };
 
     let mut total = 0usize;
     for _ in 0..iters {
         let mut output = Vec::new();
-        total += search_text_content(&text, &compiled, &config, "", &mut output);
+        total += search_text_content(&text, &compiled, &config, b"", &mut output)?;
         black_box(output.len());
     }
     Ok(total)
@@ -105,7 +116,17 @@ fn run_recursive_workload(iters: usize) -> Result<usize> {
     let config = SearchConfig {
         only_matching: false,
         use_color: false,
-        show_prefix: false,
+        colors: ColorSpecs::default(),
+        show_prefix: None,
+        null_after_name: false,
+        line_terminator: b'\n',
+        multiline: false,
+        replacement: None,
+        rewrite: None,
+        preprocessor: None,
+        search_zip: false,
+        search_archives: false,
+        max_archive_depth: 0,
         threads: 4,
     };
     let files = [tree];
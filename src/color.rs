@@ -0,0 +1,350 @@
+use std::io::{self, Write};
+
+use anyhow::{bail, Context, Result};
+
+const ANSI_RESET: &[u8] = b"\x1b[0m";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    Ansi256(u8),
+    Rgb(u8, u8, u8),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub underline: bool,
+    pub intense: bool,
+}
+
+/// Styles for every highlighted part of the output. Line number and context
+/// styles are carried for output formats that print those parts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ColorSpecs {
+    pub matched: Style,
+    pub path: Style,
+    pub line_number: Style,
+    pub separator: Style,
+    pub context: Style,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum OutputType {
+    Match,
+    Path,
+    LineNumber,
+    Separator,
+    Context,
+}
+
+impl Color {
+    fn base_index(self) -> Option<u8> {
+        match self {
+            Color::Black => Some(0),
+            Color::Red => Some(1),
+            Color::Green => Some(2),
+            Color::Yellow => Some(3),
+            Color::Blue => Some(4),
+            Color::Magenta => Some(5),
+            Color::Cyan => Some(6),
+            Color::White => Some(7),
+            Color::Ansi256(_) | Color::Rgb(..) => None,
+        }
+    }
+
+    fn from_base_index(index: u8) -> Self {
+        match index {
+            0 => Color::Black,
+            1 => Color::Red,
+            2 => Color::Green,
+            3 => Color::Yellow,
+            4 => Color::Blue,
+            5 => Color::Magenta,
+            6 => Color::Cyan,
+            _ => Color::White,
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self> {
+        let color = match value {
+            "black" => Color::Black,
+            "red" => Color::Red,
+            "green" => Color::Green,
+            "yellow" => Color::Yellow,
+            "blue" => Color::Blue,
+            "magenta" => Color::Magenta,
+            "cyan" => Color::Cyan,
+            "white" => Color::White,
+            _ => {
+                let parts = value
+                    .split(',')
+                    .map(|part| part.trim().parse::<u8>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .with_context(|| format!("unrecognized color '{value}'"))?;
+                match parts.as_slice() {
+                    [index] => Color::Ansi256(*index),
+                    [r, g, b] => Color::Rgb(*r, *g, *b),
+                    _ => bail!("unrecognized color '{value}'"),
+                }
+            }
+        };
+        Ok(color)
+    }
+
+    fn push_sgr(self, codes: &mut Vec<String>, intense: bool, base: u8) {
+        match self.base_index() {
+            Some(index) if intense => codes.push((base + 60 + index).to_string()),
+            Some(index) => codes.push((base + index).to_string()),
+            None => match self {
+                Color::Ansi256(index) => codes.push(format!("{};5;{index}", base + 8)),
+                Color::Rgb(r, g, b) => codes.push(format!("{};2;{r};{g};{b}", base + 8)),
+                _ => unreachable!("named colors have a base index"),
+            },
+        }
+    }
+}
+
+impl Style {
+    const fn fg(color: Color) -> Self {
+        Self {
+            fg: Some(color),
+            bg: None,
+            bold: false,
+            underline: false,
+            intense: false,
+        }
+    }
+
+    #[must_use]
+    pub fn is_plain(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn sgr_codes(&self) -> Vec<String> {
+        let mut codes = Vec::new();
+        if self.bold {
+            codes.push("1".to_string());
+        }
+        if self.underline {
+            codes.push("4".to_string());
+        }
+        if let Some(fg) = self.fg {
+            fg.push_sgr(&mut codes, self.intense, 30);
+        }
+        if let Some(bg) = self.bg {
+            bg.push_sgr(&mut codes, self.intense, 40);
+        }
+        codes
+    }
+
+    pub(crate) fn write_start<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        if self.is_plain() {
+            return Ok(());
+        }
+        write!(writer, "\x1b[{}m", self.sgr_codes().join(";"))
+    }
+
+    pub(crate) fn write_end<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        if self.is_plain() {
+            return Ok(());
+        }
+        writer.write_all(ANSI_RESET)
+    }
+
+    pub(crate) fn write_styled<W: Write>(&self, writer: &mut W, text: &[u8]) -> io::Result<()> {
+        self.write_start(writer)?;
+        writer.write_all(text)?;
+        self.write_end(writer)
+    }
+
+    /// Parses an SGR parameter list such as `01;31` as used by `GREP_COLORS`.
+    fn parse_sgr(value: &str) -> Option<Self> {
+        let mut style = Style::default();
+        let mut codes = value.split(';').filter(|code| !code.is_empty());
+
+        while let Some(code) = codes.next() {
+            let code: u8 = code.parse().ok()?;
+            match code {
+                0 => style = Style::default(),
+                1 => style.bold = true,
+                4 => style.underline = true,
+                22 => style.bold = false,
+                24 => style.underline = false,
+                30..=37 => style.fg = Some(Color::from_base_index(code - 30)),
+                40..=47 => style.bg = Some(Color::from_base_index(code - 40)),
+                90..=97 => {
+                    style.fg = Some(Color::from_base_index(code - 90));
+                    style.intense = true;
+                }
+                100..=107 => {
+                    style.bg = Some(Color::from_base_index(code - 100));
+                    style.intense = true;
+                }
+                38 | 48 => {
+                    let color = match codes.next()? {
+                        "5" => Color::Ansi256(codes.next()?.parse().ok()?),
+                        "2" => Color::Rgb(
+                            codes.next()?.parse().ok()?,
+                            codes.next()?.parse().ok()?,
+                            codes.next()?.parse().ok()?,
+                        ),
+                        _ => return None,
+                    };
+                    if code == 38 {
+                        style.fg = Some(color);
+                    } else {
+                        style.bg = Some(color);
+                    }
+                }
+                _ => return None,
+            }
+        }
+
+        Some(style)
+    }
+}
+
+impl Default for ColorSpecs {
+    fn default() -> Self {
+        Self {
+            matched: Style {
+                bold: true,
+                ..Style::fg(Color::Red)
+            },
+            path: Style::fg(Color::Magenta),
+            line_number: Style::fg(Color::Green),
+            separator: Style::fg(Color::Cyan),
+            context: Style::default(),
+        }
+    }
+}
+
+impl ColorSpecs {
+    /// Applies a `GREP_COLORS` value such as `ms=01;32:fn=34:se=`. Unknown
+    /// capabilities and malformed entries are ignored, as GNU grep does.
+    pub fn apply_grep_colors(&mut self, value: &str) {
+        for entry in value.split(':') {
+            let Some((key, sgr)) = entry.split_once('=') else {
+                continue;
+            };
+            let Some(style) = Style::parse_sgr(sgr) else {
+                continue;
+            };
+            match key {
+                "ms" | "mt" => self.matched = style,
+                "fn" => self.path = style,
+                "ln" => self.line_number = style,
+                "se" => self.separator = style,
+                "cx" => self.context = style,
+                _ => {}
+            }
+        }
+    }
+
+    /// Applies a `--colors` spec of the form `{type}:{attribute}:{value}` or
+    /// `{type}:none`, where type is one of `match`, `path`, `line`,
+    /// `separator` or `context`.
+    pub fn apply_spec(&mut self, spec: &str) -> Result<()> {
+        let mut parts = spec.split(':');
+        let output_type = match parts.next().unwrap_or_default() {
+            "match" => OutputType::Match,
+            "path" => OutputType::Path,
+            "line" => OutputType::LineNumber,
+            "separator" => OutputType::Separator,
+            "context" => OutputType::Context,
+            other => bail!("unrecognized color type '{other}' in '{spec}'"),
+        };
+        let style = self.style_mut(output_type);
+
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some("none"), None, None) => *style = Style::default(),
+            (Some("fg"), Some(value), None) => style.fg = Some(Color::parse(value)?),
+            (Some("bg"), Some(value), None) => style.bg = Some(Color::parse(value)?),
+            (Some("style"), Some(value), None) => match value {
+                "bold" => style.bold = true,
+                "nobold" => style.bold = false,
+                "underline" => style.underline = true,
+                "nounderline" => style.underline = false,
+                "intense" => style.intense = true,
+                "nointense" => style.intense = false,
+                other => bail!("unrecognized style '{other}' in '{spec}'"),
+            },
+            _ => bail!(
+                "expected '{{type}}:{{attribute}}:{{value}}' or '{{type}}:none', got '{spec}'"
+            ),
+        }
+
+        Ok(())
+    }
+
+    fn style_mut(&mut self, output_type: OutputType) -> &mut Style {
+        match output_type {
+            OutputType::Match => &mut self.matched,
+            OutputType::Path => &mut self.path,
+            OutputType::LineNumber => &mut self.line_number,
+            OutputType::Separator => &mut self.separator,
+            OutputType::Context => &mut self.context,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rendered(style: Style) -> String {
+        let mut output = Vec::new();
+        style.write_styled(&mut output, b"x").unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn default_match_style_is_bold_red() {
+        assert_eq!(
+            rendered(ColorSpecs::default().matched),
+            "\x1b[1;31mx\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn grep_colors_override_known_capabilities() {
+        let mut colors = ColorSpecs::default();
+        colors.apply_grep_colors("ms=01;32:fn=34:ln=38;5;208:se=:sl=7:bogus");
+
+        assert_eq!(rendered(colors.matched), "\x1b[1;32mx\x1b[0m");
+        assert_eq!(rendered(colors.path), "\x1b[34mx\x1b[0m");
+        assert_eq!(rendered(colors.line_number), "\x1b[38;5;208mx\x1b[0m");
+        assert_eq!(rendered(colors.separator), "x");
+    }
+
+    #[test]
+    fn color_specs_update_individual_attributes() {
+        let mut colors = ColorSpecs::default();
+        colors.apply_spec("match:fg:green").unwrap();
+        colors.apply_spec("match:style:nobold").unwrap();
+        colors.apply_spec("path:none").unwrap();
+        colors.apply_spec("separator:bg:10,20,30").unwrap();
+
+        assert_eq!(rendered(colors.matched), "\x1b[32mx\x1b[0m");
+        assert_eq!(rendered(colors.path), "x");
+        assert_eq!(rendered(colors.separator), "\x1b[36;48;2;10;20;30mx\x1b[0m");
+    }
+
+    #[test]
+    fn invalid_color_specs_are_rejected() {
+        let mut colors = ColorSpecs::default();
+        assert!(colors.apply_spec("match:fg:mauve").is_err());
+        assert!(colors.apply_spec("gutter:fg:red").is_err());
+        assert!(colors.apply_spec("match:fg").is_err());
+    }
+}
@@ -0,0 +1,364 @@
+use anyhow::{bail, ensure, Result};
+
+use super::DecompressedTooLarge;
+
+const MAX_CODE_BITS: usize = 15;
+const LITERAL_LENGTH_CODES: usize = 288;
+const DISTANCE_CODES: usize = 30;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+const FLAG_HEADER_CRC: u8 = 0x02;
+const FLAG_EXTRA: u8 = 0x04;
+const FLAG_NAME: u8 = 0x08;
+const FLAG_COMMENT: u8 = 0x10;
+
+/// Decodes every member of a gzip file (RFC 1952) and checks each trailer.
+/// Fails once the output grows past `limit` bytes.
+pub(crate) fn decode_gzip(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(data.len().saturating_mul(4).min(limit));
+    let mut offset = 0;
+
+    while offset < data.len() {
+        let member_start = output.len();
+        let body_start = offset + parse_member_header(&data[offset..])?;
+        let mut reader = BitReader::new(&data[body_start..]);
+        inflate(&mut reader, &mut output, limit)?;
+        let trailer_start = body_start + reader.byte_position();
+
+        let trailer = data
+            .get(trailer_start..trailer_start + 8)
+            .ok_or_else(|| anyhow::anyhow!("truncated gzip trailer"))?;
+        let expected_crc = u32::from_le_bytes(trailer[0..4].try_into()?);
+        let expected_len = u32::from_le_bytes(trailer[4..8].try_into()?);
+        let member = &output[member_start..];
+        ensure!(crc32(member) == expected_crc, "gzip CRC mismatch");
+        ensure!(member.len() as u32 == expected_len, "gzip length mismatch");
+
+        offset = trailer_start + 8;
+        // Some writers pad the end of the file with zeros.
+        if data[offset..].iter().all(|byte| *byte == 0) {
+            break;
+        }
+    }
+
+    Ok(output)
+}
+
+fn parse_member_header(data: &[u8]) -> Result<usize> {
+    ensure!(
+        data.len() >= 10 && data[0] == 0x1f && data[1] == 0x8b,
+        "not a gzip member"
+    );
+    ensure!(
+        data[2] == 8,
+        "unsupported gzip compression method {}",
+        data[2]
+    );
+
+    let flags = data[3];
+    let mut offset = 10;
+    if flags & FLAG_EXTRA != 0 {
+        let extra = data
+            .get(offset..offset + 2)
+            .ok_or_else(|| anyhow::anyhow!("truncated gzip header"))?;
+        offset += 2 + usize::from(u16::from_le_bytes([extra[0], extra[1]]));
+    }
+    for flag in [FLAG_NAME, FLAG_COMMENT] {
+        if flags & flag != 0 {
+            let terminator = data
+                .get(offset..)
+                .and_then(|rest| rest.iter().position(|byte| *byte == 0))
+                .ok_or_else(|| anyhow::anyhow!("truncated gzip header"))?;
+            offset += terminator + 1;
+        }
+    }
+    if flags & FLAG_HEADER_CRC != 0 {
+        offset += 2;
+    }
+
+    ensure!(offset <= data.len(), "truncated gzip header");
+    Ok(offset)
+}
+
+/// Inflates a raw DEFLATE stream of at most `limit` bytes.
+pub(crate) fn inflate_raw(data: &[u8], size_hint: usize, limit: usize) -> Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(size_hint.min(limit));
+    inflate(&mut BitReader::new(data), &mut output, limit)?;
+    Ok(output)
+}
+
+/// Inflates one raw DEFLATE stream (RFC 1951) into `output`, failing once
+/// `output` holds more than `limit` bytes.
+fn inflate(reader: &mut BitReader<'_>, output: &mut Vec<u8>, limit: usize) -> Result<()> {
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => inflate_stored(reader, output)?,
+            1 => {
+                let (literals, distances) = fixed_tables();
+                inflate_codes(reader, output, &literals, &distances, limit)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_tables(reader)?;
+                inflate_codes(reader, output, &literals, &distances, limit)?;
+            }
+            _ => bail!("invalid DEFLATE block type"),
+        }
+        if output.len() > limit {
+            return Err(DecompressedTooLarge { limit }.into());
+        }
+        if last {
+            return Ok(());
+        }
+    }
+}
+
+fn inflate_stored(reader: &mut BitReader<'_>, output: &mut Vec<u8>) -> Result<()> {
+    reader.align_to_byte();
+    let len = reader.bits(16)? as u16;
+    let complement = reader.bits(16)? as u16;
+    ensure!(len == !complement, "corrupt stored DEFLATE block");
+    output.extend_from_slice(reader.take_bytes(usize::from(len))?);
+    Ok(())
+}
+
+fn inflate_codes(
+    reader: &mut BitReader<'_>,
+    output: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+    limit: usize,
+) -> Result<()> {
+    loop {
+        let symbol = usize::from(reader.decode(literals)?);
+        match symbol {
+            0..=255 => output.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let index = symbol - 257;
+                ensure!(index < LENGTH_BASE.len(), "invalid DEFLATE length code");
+                let len =
+                    usize::from(LENGTH_BASE[index]) + reader.bits(LENGTH_EXTRA[index])? as usize;
+
+                let index = usize::from(reader.decode(distances)?);
+                ensure!(index < DISTANCE_BASE.len(), "invalid DEFLATE distance code");
+                let distance = usize::from(DISTANCE_BASE[index])
+                    + reader.bits(DISTANCE_EXTRA[index])? as usize;
+                ensure!(distance <= output.len(), "DEFLATE distance too far back");
+
+                let start = output.len() - distance;
+                for offset in 0..len {
+                    output.push(output[start + offset]);
+                }
+                // Back-references are how a small input inflates to a huge
+                // output, so the limit is checked after each one.
+                if output.len() > limit {
+                    return Err(DecompressedTooLarge { limit }.into());
+                }
+            }
+        }
+    }
+}
+
+fn fixed_tables() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; LITERAL_LENGTH_CODES];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    let literals = Huffman::new(&lengths).expect("fixed literal table is complete");
+    let distances = Huffman::new(&[5; DISTANCE_CODES]).expect("fixed distance table is complete");
+    (literals, distances)
+}
+
+fn dynamic_tables(reader: &mut BitReader<'_>) -> Result<(Huffman, Huffman)> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+    ensure!(
+        literal_count <= LITERAL_LENGTH_CODES && distance_count <= DISTANCE_CODES,
+        "corrupt dynamic DEFLATE header"
+    );
+
+    let mut code_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_length_table = Huffman::new(&code_lengths)?;
+
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut index = 0;
+    while index < lengths.len() {
+        let symbol = reader.decode(&code_length_table)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                ensure!(index > 0, "DEFLATE repeat with no previous length");
+                (lengths[index - 1], 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        ensure!(
+            index + repeat <= lengths.len(),
+            "DEFLATE code lengths overflow"
+        );
+        lengths[index..index + repeat].fill(value);
+        index += repeat;
+    }
+    ensure!(lengths[256] != 0, "DEFLATE block has no end-of-block code");
+
+    Ok((
+        Huffman::new(&lengths[..literal_count])?,
+        Huffman::new(&lengths[literal_count..])?,
+    ))
+}
+
+/// Canonical Huffman table stored as code counts per length plus symbols in
+/// code order, decoded one bit at a time.
+struct Huffman {
+    counts: [u16; MAX_CODE_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self> {
+        let mut counts = [0u16; MAX_CODE_BITS + 1];
+        for &len in lengths {
+            counts[usize::from(len)] += 1;
+        }
+        counts[0] = 0;
+
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - i32::from(count);
+            ensure!(left >= 0, "over-subscribed Huffman code");
+        }
+
+        let mut offsets = [0u16; MAX_CODE_BITS + 2];
+        for len in 1..=MAX_CODE_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                let slot = &mut offsets[usize::from(len)];
+                symbols[usize::from(*slot)] = symbol as u16;
+                *slot += 1;
+            }
+        }
+
+        Ok(Self { counts, symbols })
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit_buffer: u32,
+    bit_count: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            position: 0,
+            bit_buffer: 0,
+            bit_count: 0,
+        }
+    }
+
+    fn bits(&mut self, count: u8) -> Result<u32> {
+        while self.bit_count < count {
+            let Some(&byte) = self.data.get(self.position) else {
+                bail!("truncated DEFLATE stream");
+            };
+            self.bit_buffer |= u32::from(byte) << self.bit_count;
+            self.position += 1;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buffer & ((1u32 << count) - 1);
+        self.bit_buffer >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    fn decode(&mut self, table: &Huffman) -> Result<u16> {
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        for len in 1..=MAX_CODE_BITS {
+            code |= self.bits(1)? as i32;
+            let count = i32::from(table.counts[len]);
+            if code - count < first {
+                return Ok(table.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        bail!("invalid Huffman code")
+    }
+
+    fn align_to_byte(&mut self) {
+        self.bit_buffer = 0;
+        self.bit_count = 0;
+    }
+
+    fn take_bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + len)
+            .ok_or_else(|| anyhow::anyhow!("truncated stored DEFLATE block"))?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    /// Offset of the first byte not consumed by the stream. Bits left in the
+    /// buffer belong to the final, partially used byte.
+    fn byte_position(&self) -> usize {
+        self.position - usize::from(self.bit_count / 8)
+    }
+}
+
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    static TABLE: std::sync::OnceLock<[u32; 256]> = std::sync::OnceLock::new();
+    let table = TABLE.get_or_init(|| {
+        let mut table = [0u32; 256];
+        for (index, entry) in table.iter_mut().enumerate() {
+            let mut value = index as u32;
+            for _ in 0..8 {
+                value = if value & 1 == 1 {
+                    0xEDB8_8320 ^ (value >> 1)
+                } else {
+                    value >> 1
+                };
+            }
+            *entry = value;
+        }
+        table
+    });
+
+    !data.iter().fold(!0u32, |crc, byte| {
+        table[((crc ^ u32::from(*byte)) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
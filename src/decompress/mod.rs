@@ -0,0 +1,271 @@
+mod gzip;
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    process::{Command, Stdio},
+    thread,
+};
+
+use anyhow::{anyhow, Context, Result};
+use memmap2::Mmap;
+
+pub(crate) use gzip::crc32;
+
+/// Longest magic number checked by [`CompressionFormat::detect`].
+pub(crate) const MAGIC_LEN: usize = 6;
+
+/// The most bytes one compressed file, or all the members of one archive
+/// together, may decompress to. Anything larger is most likely a
+/// decompression bomb.
+pub(crate) const MAX_DECOMPRESSED_LEN: usize = 1 << 30;
+
+/// Compressed data that decompresses to more than the size limit. The file
+/// it came from is reported and skipped.
+#[derive(Debug)]
+pub(crate) struct DecompressedTooLarge {
+    limit: usize,
+}
+
+impl fmt::Display for DecompressedTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "decompressed data is larger than {} bytes", self.limit)
+    }
+}
+
+impl std::error::Error for DecompressedTooLarge {}
+
+/// Data that starts with a format's magic bytes but does not decompress.
+/// The file it came from is reported and skipped.
+#[derive(Debug)]
+pub(crate) struct InvalidCompressedData(CompressionFormat);
+
+impl fmt::Display for InvalidCompressedData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid {} data", self.0.program())
+    }
+}
+
+/// Compressed formats searched with `--search-zip`. Gzip is decoded in
+/// process, the others are piped through the matching local binary.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum CompressionFormat {
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+}
+
+impl CompressionFormat {
+    pub(crate) fn detect(header: &[u8]) -> Option<Self> {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Some(Self::Gzip)
+        } else if header.starts_with(b"BZh") {
+            Some(Self::Bzip2)
+        } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Self::Xz)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Self::Zstd)
+        } else {
+            None
+        }
+    }
+
+    fn program(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Bzip2 => "bzip2",
+            Self::Xz => "xz",
+            Self::Zstd => "zstd",
+        }
+    }
+}
+
+/// Decompresses all of `file` without reading it into memory first: gzip is
+/// decoded from a memory map, and the other formats are piped from the file
+/// into their decompressor. Only the decompressed text is buffered, up to
+/// [`MAX_DECOMPRESSED_LEN`], since the search runs over whole files.
+pub(crate) fn decompress_file(format: CompressionFormat, file: &File) -> Result<Vec<u8>> {
+    match format {
+        CompressionFormat::Gzip => {
+            let mmap = unsafe { Mmap::map(file) }?;
+            gunzip(&mmap, MAX_DECOMPRESSED_LEN)
+        }
+        _ => {
+            let mut input = file.try_clone()?;
+            input.seek(SeekFrom::Start(0))?;
+            decompress_with_program(format, input, MAX_DECOMPRESSED_LEN)
+        }
+    }
+}
+
+/// Inflates a raw DEFLATE stream, as stored in zip archives, failing with
+/// [`DecompressedTooLarge`] once the output grows past `limit` bytes.
+pub(crate) fn inflate_raw(data: &[u8], size_hint: usize, limit: usize) -> Result<Vec<u8>> {
+    gzip::inflate_raw(data, size_hint, limit)
+}
+
+/// Decompresses `data`, failing with [`DecompressedTooLarge`] once the
+/// output grows past `limit` bytes, or with [`InvalidCompressedData`] when
+/// `data` is not valid in `format`.
+pub(crate) fn decompress(format: CompressionFormat, data: &[u8], limit: usize) -> Result<Vec<u8>> {
+    match format {
+        CompressionFormat::Gzip => gunzip(data, limit),
+        _ => decompress_with_program(format, data, limit),
+    }
+}
+
+fn gunzip(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+    gzip::decode_gzip(data, limit).map_err(|err| {
+        if err.is::<DecompressedTooLarge>() {
+            err
+        } else {
+            err.context(InvalidCompressedData(CompressionFormat::Gzip))
+        }
+    })
+}
+
+/// Runs `program -dc`, copying `input` to its stdin from a separate thread
+/// so a full stdout pipe cannot deadlock the write.
+fn decompress_with_program(
+    format: CompressionFormat,
+    mut input: impl Read + Send,
+    limit: usize,
+) -> Result<Vec<u8>> {
+    let program = format.program();
+    let mut child = Command::new(program)
+        .arg("-dc")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to run {program}"))?;
+
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut decompressed = Vec::new();
+    let too_large = thread::scope(|scope| -> Result<bool> {
+        // A decompressor that exits early closes its stdin; the exit status
+        // below reports that failure, so a broken pipe is not an error here.
+        scope.spawn(move || {
+            let _ = io::copy(&mut input, &mut stdin);
+        });
+        (&mut stdout)
+            .take(limit as u64 + 1)
+            .read_to_end(&mut decompressed)?;
+        let too_large = decompressed.len() > limit;
+        if too_large {
+            // Killing it also ends the write above, which would otherwise
+            // wait for a reader that is no longer coming.
+            let _ = child.kill();
+        }
+        Ok(too_large)
+    })?;
+    if too_large {
+        let _ = child.wait();
+        return Err(DecompressedTooLarge { limit }.into());
+    }
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{program} failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .context(InvalidCompressedData(format)));
+    }
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `printf 'first line\nsecond line\n' | gzip -9n`: one fixed-Huffman block
+    // with a back-reference.
+    const FIXED_HUFFMAN: [u8; 38] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x4b, 0xcb, 0x2c, 0x2a, 0x2e,
+        0x51, 0xc8, 0xc9, 0xcc, 0x4b, 0xe5, 0x2a, 0x4e, 0x4d, 0xce, 0xcf, 0x4b, 0x81, 0xb0, 0x01,
+        0x2c, 0x5a, 0x45, 0x5d, 0x17, 0x00, 0x00, 0x00,
+    ];
+    // 32 `a`, 8 `b`, `cc\n` compressed with a Huffman-only strategy, which
+    // emits a dynamic block.
+    const DYNAMIC_HUFFMAN: [u8; 41] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x05, 0xc1, 0x31, 0x01, 0x00,
+        0x00, 0x0c, 0xc3, 0xa0, 0x7f, 0x2e, 0x49, 0xfd, 0x7b, 0x18, 0x00, 0x00, 0x00, 0x00, 0x54,
+        0x55, 0x6d, 0xf7, 0x47, 0x5a, 0x38, 0x3f, 0x2b, 0x00, 0x00, 0x00,
+    ];
+    // `stored\n` at compression level 0, which emits a stored block.
+    const STORED: [u8; 30] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x03, 0x01, 0x07, 0x00, 0xf8, 0xff,
+        0x73, 0x74, 0x6f, 0x72, 0x65, 0x64, 0x0a, 0xe2, 0x9c, 0x53, 0xa5, 0x07, 0x00, 0x00, 0x00,
+    ];
+
+    fn gunzip(data: &[u8]) -> Result<String> {
+        Ok(String::from_utf8(decompress(
+            CompressionFormat::Gzip,
+            data,
+            MAX_DECOMPRESSED_LEN,
+        )?)?)
+    }
+
+    #[test]
+    fn decodes_every_deflate_block_type() {
+        assert_eq!(gunzip(&FIXED_HUFFMAN).unwrap(), "first line\nsecond line\n");
+        assert_eq!(
+            gunzip(&DYNAMIC_HUFFMAN).unwrap(),
+            format!("{}{}cc\n", "a".repeat(32), "b".repeat(8))
+        );
+        assert_eq!(gunzip(&STORED).unwrap(), "stored\n");
+    }
+
+    #[test]
+    fn concatenated_members_decode_in_order() {
+        let data = [&STORED[..], &FIXED_HUFFMAN[..]].concat();
+        assert_eq!(gunzip(&data).unwrap(), "stored\nfirst line\nsecond line\n");
+    }
+
+    #[test]
+    fn rejects_corrupt_gzip_data() {
+        let mut corrupt = FIXED_HUFFMAN;
+        corrupt[30] ^= 0xff;
+        assert!(gunzip(&corrupt).is_err());
+        assert!(gunzip(&FIXED_HUFFMAN[..20]).is_err());
+    }
+
+    #[test]
+    fn stops_decoding_past_the_size_limit() {
+        // `DYNAMIC_HUFFMAN` decodes to exactly 43 bytes.
+        let decoded = decompress(CompressionFormat::Gzip, &DYNAMIC_HUFFMAN, 43).unwrap();
+        assert_eq!(decoded.len(), 43);
+
+        for data in [&DYNAMIC_HUFFMAN[..], &STORED[..]] {
+            let err = decompress(CompressionFormat::Gzip, data, 6).unwrap_err();
+            assert!(err.is::<DecompressedTooLarge>(), "{err:#}");
+            assert_eq!(err.to_string(), "decompressed data is larger than 6 bytes");
+        }
+    }
+
+    #[test]
+    fn detects_formats_by_magic_bytes() {
+        assert_eq!(
+            CompressionFormat::detect(&[0x1f, 0x8b, 0x08]),
+            Some(CompressionFormat::Gzip)
+        );
+        assert_eq!(
+            CompressionFormat::detect(b"BZh91AY"),
+            Some(CompressionFormat::Bzip2)
+        );
+        assert_eq!(
+            CompressionFormat::detect(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]),
+            Some(CompressionFormat::Xz)
+        );
+        assert_eq!(
+            CompressionFormat::detect(&[0x28, 0xb5, 0x2f, 0xfd]),
+            Some(CompressionFormat::Zstd)
+        );
+        assert_eq!(CompressionFormat::detect(b"plain text"), None);
+    }
+}
@@ -1,20 +1,33 @@
-use regex_automata::meta::Regex;
+use aho_corasick::AhoCorasick;
+use regex_automata::{meta::Regex, util::syntax as automata_syntax, PatternID};
 
-use super::{LineCandidate, RegexMatch};
+use super::{syntax, LineCandidate, RegexCaptures, RegexMatch, RegexOptions};
 
 pub(crate) struct AutomataSearch {
     regex: Regex,
-    literal_prefix: Option<String>,
+    prefilter: Option<RequiredLiterals>,
+}
+
+/// Literals one of which every match contains, anywhere in the match. A
+/// line without any of them cannot match.
+enum RequiredLiterals {
+    One(String),
+    Any(AhoCorasick),
 }
 
 impl AutomataSearch {
-    pub(crate) fn new(pattern: &str) -> Self {
-        let regex = Regex::new(pattern)
-            .unwrap_or_else(|err| panic!("invalid regex for automata engine: {err}"));
-        Self {
+    pub(crate) fn new(pattern: &str, options: RegexOptions) -> anyhow::Result<Self> {
+        let regex = Regex::builder()
+            .syntax(
+                automata_syntax::Config::new()
+                    .multi_line(options.multiline)
+                    .dot_matches_new_line(options.multiline && options.dot_matches_new_line),
+            )
+            .build(pattern)?;
+        Ok(Self {
             regex,
-            literal_prefix: extract_literal_prefix(pattern),
-        }
+            prefilter: RequiredLiterals::extract(pattern),
+        })
     }
 
     pub(crate) fn find_all(&self, input: &str) -> Vec<RegexMatch> {
@@ -27,39 +40,55 @@ impl AutomataSearch {
             .collect()
     }
 
+    pub(crate) fn find_all_captures(&self, input: &str) -> Vec<RegexCaptures> {
+        self.regex
+            .captures_iter(input)
+            .map(|captures| {
+                RegexCaptures::new(
+                    (0..captures.group_len())
+                        .map(|idx| {
+                            captures.get_group(idx).map(|span| RegexMatch {
+                                start: span.start,
+                                end: span.end,
+                            })
+                        })
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
+    pub(crate) fn captures_len(&self) -> usize {
+        self.regex.group_info().group_len(PatternID::ZERO)
+    }
+
+    pub(crate) fn capture_index(&self, name: &str) -> Option<usize> {
+        self.regex.group_info().to_index(PatternID::ZERO, name)
+    }
+
     pub(crate) fn find_candidate_line(&self, input: &str, at: usize) -> Option<LineCandidate> {
-        let prefix = self.literal_prefix.as_deref()?;
-        let offset = input.get(at..)?.find(prefix)? + at;
+        let offset = self.prefilter.as_ref()?.find(input.get(at..)?)? + at;
         Some(LineCandidate::Candidate(offset))
     }
 
     pub(crate) fn supports_candidate_lines(&self) -> bool {
-        self.literal_prefix.is_some()
+        self.prefilter.is_some()
     }
 }
 
-fn extract_literal_prefix(pattern: &str) -> Option<String> {
-    let mut literal = String::new();
-    let mut chars = pattern
-        .strip_prefix('^')
-        .unwrap_or(pattern)
-        .chars()
-        .peekable();
-
-    while let Some(ch) = chars.next() {
-        match ch {
-            '\\' => {
-                let escaped = chars.next()?;
-                if escaped.is_ascii_alphanumeric() {
-                    break;
-                }
-                literal.push(escaped);
-            }
-            '.' | '[' | '(' | ')' | '|' | '?' | '+' | '*' | '{' => break,
-            '$' if chars.peek().is_none() => break,
-            _ => literal.push(ch),
+impl RequiredLiterals {
+    fn extract(pattern: &str) -> Option<Self> {
+        let mut literals = syntax::parse(pattern).ok()?.required_literals()?;
+        if literals.len() == 1 {
+            return literals.pop().map(RequiredLiterals::One);
         }
+        AhoCorasick::new(&literals).ok().map(RequiredLiterals::Any)
     }
 
-    (!literal.is_empty()).then_some(literal)
+    fn find(&self, haystack: &str) -> Option<usize> {
+        match self {
+            RequiredLiterals::One(literal) => haystack.find(literal.as_str()),
+            RequiredLiterals::Any(automaton) => Some(automaton.find(haystack)?.start()),
+        }
+    }
 }
@@ -1,6 +1,17 @@
-use crate::engine::RegexMatch;
+use std::{
+    fmt,
+    sync::{Arc, OnceLock},
+};
 
-use super::{parser::Parser, runtime::CompiledBackreferenceRegex};
+use anyhow::{ensure, Context, Result};
+use regex_automata::{
+    dfa::{dense, dense::DFA, Automaton, StartKind},
+    Anchored, Input,
+};
+
+use crate::engine::{syntax, BacktrackLimitExceeded, RegexMatch, RegexOptions, SearchBudget};
+
+use super::{lower::lower, runtime::CompiledBackreferenceRegex};
 
 #[derive(Debug)]
 pub(super) enum Pattern {
@@ -9,45 +20,55 @@ pub(super) enum Pattern {
     Alphanumeric(Count),
     Wildcard(Count),
     CharGroup(CharGroup, Count),
+    /// A parenthesized group. `idx` is the zero-based capture index, or
+    /// `None` for a non-capturing `(?:...)` group.
     Alternation {
-        idx: usize,
+        idx: Option<usize>,
         alternatives: Vec<Vec<Pattern>>,
         count: Count,
     },
     CapturedGroup {
-        idx: usize,
+        idx: Option<usize>,
+        patterns: Vec<Pattern>,
+        count: Count,
+    },
+    /// An atomic group `(?>...)`, or a possessive quantifier such as `a*+`.
+    /// Once the body matches, the backtracking points it left are dropped.
+    AtomicGroup {
         patterns: Vec<Pattern>,
         count: Count,
     },
+    /// A zero-width `(?=...)`, `(?!...)`, `(?<=...)` or `(?<!...)`
+    /// assertion. Lookbehind bodies must have a fixed width.
+    Lookaround {
+        ahead: bool,
+        negated: bool,
+        patterns: Vec<Pattern>,
+    },
     Backreference(usize),
+    WordBoundary,
+    NotWordBoundary,
+    /// `^`: the start of the input, or of a line in multiline mode.
+    StartAnchor,
+    /// `$`: the end of the input, or of a line in multiline mode.
+    EndAnchor,
 }
 
 impl Pattern {
-    pub(super) fn parse(
-        regex: &str,
-    ) -> (
-        Vec<Pattern>,
-        bool, /* start_anchor */
-        bool, /* end_anchor */
-    ) {
-        let start = regex.starts_with('^');
-        let end = regex.ends_with('$');
-        let mut chars = regex.chars().peekable();
-
-        if start {
-            chars.next();
-        }
-        if end {
-            chars.next_back();
-        }
-
-        let mut parser = Parser::new();
-        let mut patterns = Vec::new();
-        while chars.peek().is_some() {
-            patterns.push(parser.parse(&mut chars));
-        }
+    pub(super) fn parse(regex: &str) -> Result<ParsedPattern> {
+        lower(&syntax::parse(regex)?)
+    }
 
-        (patterns, start, end)
+    /// Whether the pattern is an assertion that consumes no input.
+    pub(super) fn is_zero_width(&self) -> bool {
+        matches!(
+            self,
+            Pattern::WordBoundary
+                | Pattern::NotWordBoundary
+                | Pattern::StartAnchor
+                | Pattern::EndAnchor
+                | Pattern::Lookaround { .. }
+        )
     }
 
     pub(super) fn count(&self) -> Count {
@@ -58,12 +79,26 @@ impl Pattern {
             | Pattern::Wildcard(count)
             | Pattern::CharGroup(_, count)
             | Pattern::Alternation { count, .. }
-            | Pattern::CapturedGroup { count, .. } => *count,
-            Pattern::Backreference(_) => Count::One,
+            | Pattern::CapturedGroup { count, .. }
+            | Pattern::AtomicGroup { count, .. } => *count,
+            Pattern::Backreference(_)
+            | Pattern::WordBoundary
+            | Pattern::NotWordBoundary
+            | Pattern::StartAnchor
+            | Pattern::EndAnchor
+            | Pattern::Lookaround { .. } => Count::One,
         }
     }
 }
 
+pub(super) struct ParsedPattern {
+    pub(super) patterns: Vec<Pattern>,
+    pub(super) start_anchor: bool,
+    pub(super) end_anchor: bool,
+    /// Named groups with their zero-based capture indices.
+    pub(super) group_names: Vec<(String, usize)>,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub(super) enum Count {
     One,
@@ -73,9 +108,28 @@ pub(super) enum Count {
     Exact(usize),
     AtLeast(usize),
     Range(usize, usize),
+    /// A non-greedy repetition such as `*?` or `{n,m}?`, which prefers the
+    /// fewest repetitions.
+    Lazy {
+        min: usize,
+        max: Option<usize>,
+    },
 }
 
 impl Count {
+    /// The non-greedy form of this count. Fixed counts are unchanged, since
+    /// they have nothing to prefer.
+    pub(super) fn into_lazy(self) -> Self {
+        match self.repetition_bounds() {
+            (min, Some(max)) if min == max => self,
+            (min, max) => Count::Lazy { min, max },
+        }
+    }
+
+    pub(super) fn is_lazy(self) -> bool {
+        matches!(self, Count::Lazy { .. })
+    }
+
     pub(super) fn is_exactly_one(self) -> bool {
         matches!(self, Count::One | Count::Exact(1))
     }
@@ -97,18 +151,22 @@ impl Count {
             Count::Exact(n) => (n, Some(n)),
             Count::AtLeast(min) => (min, None),
             Count::Range(min, max) => (min, Some(max)),
+            Count::Lazy { min, max } => (min, max),
         }
     }
 
     pub(super) fn combine(&self, other: &Self) -> Self {
         let (left_min, left_max) = self.repetition_bounds();
         let (right_min, right_max) = other.repetition_bounds();
-        let min = left_min + right_min;
         let max = match (left_max, right_max) {
             (Some(left), Some(right)) => Some(left + right),
             _ => None,
         };
+        Count::from_bounds(left_min + right_min, max)
+    }
 
+    /// The greedy count for `{min,max}`.
+    pub(super) fn from_bounds(min: usize, max: Option<usize>) -> Self {
         match (min, max) {
             (1, Some(1)) => Count::One,
             (0, Some(1)) => Count::ZeroOrOne,
@@ -121,29 +179,184 @@ impl Count {
     }
 }
 
+/// A bracket expression. ASCII membership is precomputed into a table;
+/// non-ASCII characters are checked against explicit ranges and Unicode
+/// property classes.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(super) struct CharGroup {
     negated: bool,
-    ascii_members: [bool; 128],
+    ascii_members: u128,
+    ranges: Vec<(char, char)>,
+    classes: Vec<(UnicodeClass, bool /* negated */)>,
 }
 
 impl CharGroup {
-    pub(super) fn new(negated: bool, members: &str) -> Self {
-        let mut ascii_members = [false; 128];
-        for member in members.chars() {
-            if member.is_ascii() {
-                ascii_members[member as usize] = true;
+    pub(super) fn new(negated: bool) -> Self {
+        Self {
+            negated,
+            ascii_members: 0,
+            ranges: Vec::new(),
+            classes: Vec::new(),
+        }
+    }
+
+    pub(super) fn add_range(&mut self, start: char, end: char) {
+        for ascii in (start as u32)..=(end as u32).min(127) {
+            self.ascii_members |= 1 << ascii;
+        }
+        if !end.is_ascii() {
+            self.ranges.push((start.max('\u{80}'), end));
+        }
+    }
+
+    /// Adds every ASCII character accepted by `pred`, or, when `negated`,
+    /// every character it rejects including all of non-ASCII.
+    pub(super) fn add_ascii_class(&mut self, pred: impl Fn(char) -> bool, negated: bool) {
+        for ascii in 0..128u8 {
+            if pred(char::from(ascii)) != negated {
+                self.ascii_members |= 1 << ascii;
             }
         }
+        if negated {
+            self.ranges.push(('\u{80}', char::MAX));
+        }
+    }
 
-        Self {
-            negated,
-            ascii_members,
+    pub(super) fn add_unicode_class(&mut self, class: UnicodeClass, negated: bool) {
+        for ascii in 0..128u8 {
+            if class.matches(char::from(ascii)) != negated {
+                self.ascii_members |= 1 << ascii;
+            }
         }
+        self.classes.push((class, negated));
+    }
+
+    /// Whether every character the group accepts is a single byte.
+    pub(super) fn is_ascii_only(&self) -> bool {
+        !self.negated && self.ranges.is_empty() && self.classes.is_empty()
     }
 
     pub(super) fn matches(&self, c: char) -> bool {
-        c.is_ascii_alphanumeric() && (self.ascii_members[c as usize] ^ self.negated)
+        let member = if c.is_ascii() {
+            self.ascii_members & (1 << c as u32) != 0
+        } else {
+            self.ranges
+                .iter()
+                .any(|(start, end)| (*start..=*end).contains(&c))
+                || self
+                    .classes
+                    .iter()
+                    .any(|(class, negated)| class.matches(c) != *negated)
+        };
+        member != self.negated
+    }
+}
+
+/// A Unicode class such as `\w` or `\p{Greek}`, compiled with
+/// `regex_automata` so that membership agrees exactly with the automata engine.
+/// A character is tested by running its UTF-8 encoding through an anchored DFA.
+#[derive(Clone)]
+pub(super) struct UnicodeClass {
+    syntax: Arc<str>,
+    dfa: Arc<DFA<Vec<u32>>>,
+}
+
+impl UnicodeClass {
+    /// `\p{name}` or `\pN`, with the property names `regex_automata` accepts.
+    pub(super) fn property(name: &str) -> Result<Self> {
+        ensure!(
+            name.chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, ' ' | '_' | '-' | '=' | ':')),
+            "invalid Unicode class name: {name}"
+        );
+        Self::compile(&format!("\\p{{{name}}}"))
+            .with_context(|| format!("unsupported Unicode class: {name}"))
+    }
+
+    pub(super) fn digit() -> &'static Self {
+        static DIGIT: OnceLock<UnicodeClass> = OnceLock::new();
+        DIGIT.get_or_init(|| Self::builtin(r"\d"))
+    }
+
+    pub(super) fn word() -> &'static Self {
+        static WORD: OnceLock<UnicodeClass> = OnceLock::new();
+        WORD.get_or_init(|| Self::builtin(r"\w"))
+    }
+
+    pub(super) fn space() -> &'static Self {
+        static SPACE: OnceLock<UnicodeClass> = OnceLock::new();
+        SPACE.get_or_init(|| Self::builtin(r"\s"))
+    }
+
+    fn builtin(syntax: &str) -> Self {
+        Self::compile(syntax).expect("built-in class compiles")
+    }
+
+    fn compile(syntax: &str) -> Result<Self> {
+        let dfa = dense::Builder::new()
+            .configure(dense::Config::new().start_kind(StartKind::Anchored))
+            .build(syntax)?;
+        Ok(Self {
+            syntax: syntax.into(),
+            dfa: Arc::new(dfa),
+        })
+    }
+
+    pub(super) fn matches(&self, c: char) -> bool {
+        let mut buf = [0; 4];
+        let input = Input::new(c.encode_utf8(&mut buf).as_bytes()).anchored(Anchored::Yes);
+        let Ok(mut state) = self.dfa.start_state_forward(&input) else {
+            return false;
+        };
+        for byte in input.haystack() {
+            state = self.dfa.next_state(state, *byte);
+            if self.dfa.is_dead_state(state) {
+                return false;
+            }
+        }
+        self.dfa.is_match_state(self.dfa.next_eoi_state(state))
+    }
+}
+
+impl PartialEq for UnicodeClass {
+    fn eq(&self, other: &Self) -> bool {
+        self.syntax == other.syntax
+    }
+}
+
+impl Eq for UnicodeClass {}
+
+impl fmt::Debug for UnicodeClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.syntax)
+    }
+}
+
+/// The characters matched by `\d`: Unicode decimal digits.
+pub(super) fn is_digit_char(c: char) -> bool {
+    if c.is_ascii() {
+        c.is_ascii_digit()
+    } else {
+        UnicodeClass::digit().matches(c)
+    }
+}
+
+/// The characters matched by `\w`: Unicode letters, marks, digits and
+/// connector punctuation.
+pub(super) fn is_word_char(c: char) -> bool {
+    if c.is_ascii() {
+        c.is_ascii_alphanumeric() || c == '_'
+    } else {
+        UnicodeClass::word().matches(c)
+    }
+}
+
+/// The characters matched by `\s`: Unicode white space.
+pub(super) fn is_space_char(c: char) -> bool {
+    if c.is_ascii() {
+        c.is_ascii_whitespace() || c == '\x0b'
+    } else {
+        UnicodeClass::space().matches(c)
     }
 }
 
@@ -153,13 +366,17 @@ pub(super) struct CaptureSpan {
     pub(super) end: usize,
 }
 
-pub(super) fn compile_backreference_regex(regex: &str) -> CompiledBackreferenceRegex {
-    CompiledBackreferenceRegex::new(regex)
+pub(super) fn compile_backreference_regex(
+    regex: &str,
+    options: RegexOptions,
+) -> Result<CompiledBackreferenceRegex> {
+    CompiledBackreferenceRegex::new(regex, options)
 }
 
 pub(super) fn find_all_backreference_regex_spans_compiled(
     input_line: &str,
     regex: &CompiledBackreferenceRegex,
-) -> Vec<RegexMatch> {
-    regex.find_all(input_line)
+    budget: &SearchBudget,
+) -> Result<Vec<RegexMatch>, BacktrackLimitExceeded> {
+    regex.find_all(input_line, budget)
 }
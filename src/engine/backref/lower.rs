@@ -0,0 +1,257 @@
+use anyhow::{bail, Context, Result};
+
+use crate::engine::syntax::{
+    Assertion, Ast, AstKind, Bracket, ClassItem, Greed, Group, GroupKind, PerlClass, Repetition,
+};
+
+use super::ast::{
+    is_space_char, is_word_char, CharGroup, Count, ParsedPattern, Pattern, UnicodeClass,
+};
+
+/// Lowers a parsed pattern into the backreference engine's patterns. Anchors
+/// at the very ends of a top-level sequence are lifted out into `start_anchor`
+/// and `end_anchor`; any others stay as assertions.
+pub(super) fn lower(ast: &Ast) -> Result<ParsedPattern> {
+    let mut lowering = Lowering {
+        group_names: Vec::new(),
+    };
+    let mut items = ast.items();
+    let start_anchor = matches!(
+        items.first(),
+        Some(Ast {
+            kind: AstKind::Assertion(Assertion::Start),
+            ..
+        })
+    );
+    if start_anchor {
+        items = &items[1..];
+    }
+    let end_anchor = matches!(
+        items.last(),
+        Some(Ast {
+            kind: AstKind::Assertion(Assertion::End),
+            ..
+        })
+    );
+    if end_anchor {
+        items = &items[..items.len() - 1];
+    }
+
+    let patterns = items
+        .iter()
+        .map(|item| lowering.lower(item))
+        .collect::<Result<_>>()?;
+    Ok(ParsedPattern {
+        patterns,
+        start_anchor,
+        end_anchor,
+        group_names: lowering.group_names,
+    })
+}
+
+struct Lowering {
+    group_names: Vec<(String, usize)>,
+}
+
+impl Lowering {
+    fn lower(&mut self, ast: &Ast) -> Result<Pattern> {
+        Ok(match &ast.kind {
+            AstKind::Empty | AstKind::Concat(_) => Pattern::CapturedGroup {
+                idx: None,
+                patterns: self.sequence(ast)?,
+                count: Count::One,
+            },
+            AstKind::Literal(ch) => Pattern::Literal(*ch, Count::One),
+            AstKind::Dot => Pattern::Wildcard(Count::One),
+            AstKind::Class(ClassItem::Perl(PerlClass::Digit, false)) => Pattern::Digit(Count::One),
+            AstKind::Class(ClassItem::Perl(PerlClass::Word, false)) => {
+                Pattern::Alphanumeric(Count::One)
+            }
+            AstKind::Class(item) => {
+                let mut group = CharGroup::new(false);
+                add_class_item(&mut group, item)
+                    .with_context(|| format!("invalid class at {}", ast.span))?;
+                Pattern::CharGroup(group, Count::One)
+            }
+            AstKind::Bracket(bracket) => Pattern::CharGroup(
+                char_group(bracket).with_context(|| format!("invalid class at {}", ast.span))?,
+                Count::One,
+            ),
+            AstKind::Assertion(Assertion::Start) => Pattern::StartAnchor,
+            AstKind::Assertion(Assertion::End) => Pattern::EndAnchor,
+            AstKind::Assertion(Assertion::WordBoundary) => Pattern::WordBoundary,
+            AstKind::Assertion(Assertion::NotWordBoundary) => Pattern::NotWordBoundary,
+            AstKind::Backreference(group) => Pattern::Backreference(*group),
+            AstKind::Flags(flags)
+            | AstKind::Group(Group {
+                kind: GroupKind::Flags(flags),
+                ..
+            }) => bail!(
+                "group flags (?{flags}) are not supported with backreferences at {}",
+                ast.span
+            ),
+            AstKind::Repetition(repetition) => self.lower_repetition(repetition)?,
+            AstKind::Group(group) => self.lower_group(group)?,
+            AstKind::Alternation(_) => self.group(None, ast)?,
+        })
+    }
+
+    fn lower_repetition(&mut self, repetition: &Repetition) -> Result<Pattern> {
+        // Like `regex_automata`, drop a `{0}` body entirely, capture groups
+        // and all, so the group count and the names agree between engines.
+        if repetition.max == Some(0) {
+            return Ok(Pattern::CapturedGroup {
+                idx: None,
+                patterns: Vec::new(),
+                count: Count::One,
+            });
+        }
+        let count = Count::from_bounds(repetition.min, repetition.max);
+        let count = match repetition.greed {
+            Greed::Lazy => count.into_lazy(),
+            Greed::Greedy | Greed::Possessive => count,
+        };
+        let pattern = with_count(self.lower(&repetition.ast)?, count);
+        Ok(match repetition.greed {
+            Greed::Possessive => Pattern::AtomicGroup {
+                patterns: vec![pattern],
+                count: Count::One,
+            },
+            Greed::Greedy | Greed::Lazy => pattern,
+        })
+    }
+
+    fn lower_group(&mut self, group: &Group) -> Result<Pattern> {
+        Ok(match &group.kind {
+            GroupKind::Capture { index, name } => {
+                if let Some(name) = name {
+                    self.group_names.push((name.clone(), *index));
+                }
+                self.group(Some(*index), &group.ast)?
+            }
+            GroupKind::NonCapturing => self.group(None, &group.ast)?,
+            GroupKind::Atomic => Pattern::AtomicGroup {
+                patterns: self.sequence(&group.ast)?,
+                count: Count::One,
+            },
+            GroupKind::Lookaround { ahead, negated } => Pattern::Lookaround {
+                ahead: *ahead,
+                negated: *negated,
+                patterns: self.sequence(&group.ast)?,
+            },
+            GroupKind::Flags(_) => unreachable!("flag groups are rejected by `lower`"),
+        })
+    }
+
+    /// A group around `ast`, which becomes an `Alternation` when `ast` has
+    /// several branches.
+    fn group(&mut self, idx: Option<usize>, ast: &Ast) -> Result<Pattern> {
+        Ok(match &ast.kind {
+            AstKind::Alternation(branches) => Pattern::Alternation {
+                idx,
+                alternatives: branches
+                    .iter()
+                    .map(|branch| self.sequence(branch))
+                    .collect::<Result<_>>()?,
+                count: Count::One,
+            },
+            _ => Pattern::CapturedGroup {
+                idx,
+                patterns: self.sequence(ast)?,
+                count: Count::One,
+            },
+        })
+    }
+
+    fn sequence(&mut self, ast: &Ast) -> Result<Vec<Pattern>> {
+        match &ast.kind {
+            AstKind::Empty => Ok(Vec::new()),
+            AstKind::Concat(items) => items.iter().map(|item| self.lower(item)).collect(),
+            _ => Ok(vec![self.lower(ast)?]),
+        }
+    }
+}
+
+/// Applies `count` to `pattern`, wrapping patterns that cannot carry a count,
+/// such as backreferences, in a non-capturing group.
+fn with_count(pattern: Pattern, count: Count) -> Pattern {
+    match pattern {
+        Pattern::Literal(ch, _) => Pattern::Literal(ch, count),
+        Pattern::Digit(_) => Pattern::Digit(count),
+        Pattern::Alphanumeric(_) => Pattern::Alphanumeric(count),
+        Pattern::Wildcard(_) => Pattern::Wildcard(count),
+        Pattern::CharGroup(group, _) => Pattern::CharGroup(group, count),
+        Pattern::Alternation {
+            idx, alternatives, ..
+        } => Pattern::Alternation {
+            idx,
+            alternatives,
+            count,
+        },
+        Pattern::CapturedGroup { idx, patterns, .. } => Pattern::CapturedGroup {
+            idx,
+            patterns,
+            count,
+        },
+        Pattern::AtomicGroup { patterns, .. } => Pattern::AtomicGroup { patterns, count },
+        other @ (Pattern::Lookaround { .. }
+        | Pattern::Backreference(_)
+        | Pattern::WordBoundary
+        | Pattern::NotWordBoundary
+        | Pattern::StartAnchor
+        | Pattern::EndAnchor) => Pattern::CapturedGroup {
+            idx: None,
+            patterns: vec![other],
+            count,
+        },
+    }
+}
+
+fn char_group(bracket: &Bracket) -> Result<CharGroup> {
+    let mut group = CharGroup::new(bracket.negated);
+    for item in &bracket.items {
+        add_class_item(&mut group, item)?;
+    }
+    Ok(group)
+}
+
+fn add_class_item(group: &mut CharGroup, item: &ClassItem) -> Result<()> {
+    match item {
+        ClassItem::Range(start, end) => group.add_range(*start, *end),
+        ClassItem::Perl(class, negated) => {
+            let class = match class {
+                PerlClass::Digit => UnicodeClass::digit(),
+                PerlClass::Word => UnicodeClass::word(),
+                PerlClass::Space => UnicodeClass::space(),
+            };
+            group.add_unicode_class(class.clone(), *negated);
+        }
+        ClassItem::Unicode(name, negated) => {
+            group.add_unicode_class(UnicodeClass::property(name)?, *negated);
+        }
+        ClassItem::Posix(name, negated) => group.add_ascii_class(posix_class(name)?, *negated),
+    }
+    Ok(())
+}
+
+/// The members of a POSIX class such as `[:alpha:]`. POSIX classes are
+/// ASCII-only.
+fn posix_class(name: &str) -> Result<fn(char) -> bool> {
+    Ok(match name {
+        "alnum" => |c| c.is_ascii_alphanumeric(),
+        "alpha" => |c| c.is_ascii_alphabetic(),
+        "ascii" => |c| c.is_ascii(),
+        "blank" => |c| c == ' ' || c == '\t',
+        "cntrl" => |c| c.is_ascii_control(),
+        "digit" => |c| c.is_ascii_digit(),
+        "graph" => |c| c.is_ascii_graphic(),
+        "lower" => |c| c.is_ascii_lowercase(),
+        "print" => |c| c.is_ascii_graphic() || c == ' ',
+        "punct" => |c| c.is_ascii_punctuation(),
+        "space" => |c| c.is_ascii() && is_space_char(c),
+        "upper" => |c| c.is_ascii_uppercase(),
+        "word" => |c| c.is_ascii() && is_word_char(c),
+        "xdigit" => |c| c.is_ascii_hexdigit(),
+        unknown => bail!("Unknown POSIX class: [:{unknown}:]"),
+    })
+}
@@ -1,32 +1,64 @@
 mod ast;
-mod parser;
+mod lower;
+mod prefilter;
 mod runtime;
 
 use ast::{compile_backreference_regex, find_all_backreference_regex_spans_compiled};
+use prefilter::Prefilter;
 use runtime::CompiledBackreferenceRegex;
 
-use super::{LineCandidate, RegexMatch};
+use super::{
+    BacktrackLimitExceeded, LineCandidate, RegexCaptures, RegexMatch, RegexOptions, SearchBudget,
+};
 
 pub(crate) struct BackreferenceSearch {
     regex: CompiledBackreferenceRegex,
+    /// Finds candidate lines for line-oriented search. Multiline searches
+    /// run over the whole buffer and have none.
+    prefilter: Option<Prefilter>,
 }
 
 impl BackreferenceSearch {
-    pub(crate) fn new(pattern: &str) -> Self {
-        Self {
-            regex: compile_backreference_regex(pattern),
-        }
+    /// Compiles `pattern`, or reports the syntax the backreference engine does
+    /// not support.
+    pub(crate) fn new(pattern: &str, options: RegexOptions) -> anyhow::Result<Self> {
+        let regex = compile_backreference_regex(pattern, options)?;
+        let prefilter = (!options.multiline)
+            .then(|| Prefilter::new(pattern, regex.required_literal()))
+            .flatten();
+        Ok(Self { regex, prefilter })
     }
 
-    pub(crate) fn find_all(&self, input: &str) -> Vec<RegexMatch> {
-        find_all_backreference_regex_spans_compiled(input, &self.regex)
+    pub(crate) fn find_all(
+        &self,
+        input: &str,
+        budget: &SearchBudget,
+    ) -> Result<Vec<RegexMatch>, BacktrackLimitExceeded> {
+        find_all_backreference_regex_spans_compiled(input, &self.regex, budget)
     }
 
-    pub(crate) fn find_candidate_line(&self, _input: &str, _at: usize) -> Option<LineCandidate> {
-        None
+    pub(crate) fn find_all_captures(
+        &self,
+        input: &str,
+        budget: &SearchBudget,
+    ) -> Result<Vec<RegexCaptures>, BacktrackLimitExceeded> {
+        self.regex.find_all_captures(input, budget)
+    }
+
+    pub(crate) fn captures_len(&self) -> usize {
+        self.regex.captures_len()
+    }
+
+    pub(crate) fn capture_index(&self, name: &str) -> Option<usize> {
+        self.regex.capture_index(name)
+    }
+
+    pub(crate) fn find_candidate_line(&self, input: &str, at: usize) -> Option<LineCandidate> {
+        let offset = self.prefilter.as_ref()?.find(input, at)?;
+        Some(LineCandidate::Candidate(offset))
     }
 
     pub(crate) fn supports_candidate_lines(&self) -> bool {
-        false
+        self.prefilter.is_some()
     }
 }
@@ -0,0 +1,162 @@
+use regex_automata::meta::Regex;
+
+use crate::engine::syntax::{self, Ast, AstKind, GroupKind};
+
+/// Approximations longer than this are not worth compiling: copying groups
+/// into nested references grows the pattern exponentially.
+const MAX_APPROXIMATION_LEN: usize = 4096;
+
+/// Finds where a match may start, so the VM only runs on candidate lines.
+pub(super) enum Prefilter {
+    /// The pattern with each `\N` replaced by a copy of group N, and with
+    /// lookarounds, anchors and word boundaries dropped. It matches
+    /// everything the pattern does, and usually little else.
+    Approximation(Regex),
+    /// A literal every match contains.
+    Literal(String),
+}
+
+impl Prefilter {
+    pub(super) fn new(pattern: &str, required_literal: Option<&str>) -> Option<Self> {
+        approximate(pattern)
+            .map(Prefilter::Approximation)
+            .or_else(|| required_literal.map(|literal| Prefilter::Literal(literal.to_string())))
+    }
+
+    /// The offset of the next possible match at or after `at`.
+    pub(super) fn find(&self, input: &str, at: usize) -> Option<usize> {
+        match self {
+            Prefilter::Approximation(regex) => Some(regex.find(input.get(at..)?)?.start() + at),
+            Prefilter::Literal(literal) => Some(input.get(at..)?.find(literal.as_str())? + at),
+        }
+    }
+}
+
+/// Compiles the backreference-free approximation of `pattern`, or returns
+/// `None` when there is none worth searching for.
+fn approximate(pattern: &str) -> Option<Regex> {
+    let ast = syntax::parse(pattern).ok()?;
+    let mut approximation = Approximation {
+        pattern,
+        groups: Vec::new(),
+        open: Vec::new(),
+        out: String::new(),
+    };
+    approximation.collect_groups(&ast);
+    approximation.render(&ast)?;
+
+    let regex = Regex::new(&approximation.out).ok()?;
+    // One that matches the empty string would make every line a candidate.
+    (!regex.is_match("")).then_some(regex)
+}
+
+struct Approximation<'a> {
+    pattern: &'a str,
+    /// The body of each capture group, by zero-based index.
+    groups: Vec<Option<&'a Ast>>,
+    /// The groups whose copies are being rendered.
+    open: Vec<usize>,
+    out: String,
+}
+
+impl<'a> Approximation<'a> {
+    fn collect_groups(&mut self, ast: &'a Ast) {
+        match &ast.kind {
+            AstKind::Group(group) => {
+                if let GroupKind::Capture { index, .. } = group.kind {
+                    if self.groups.len() <= index {
+                        self.groups.resize(index + 1, None);
+                    }
+                    self.groups[index] = Some(&group.ast);
+                }
+                self.collect_groups(&group.ast);
+            }
+            AstKind::Repetition(repetition) => self.collect_groups(&repetition.ast),
+            AstKind::Concat(asts) | AstKind::Alternation(asts) => {
+                for ast in asts {
+                    self.collect_groups(ast);
+                }
+            }
+            AstKind::Empty
+            | AstKind::Literal(_)
+            | AstKind::Dot
+            | AstKind::Class(_)
+            | AstKind::Bracket(_)
+            | AstKind::Assertion(_)
+            | AstKind::Backreference(_)
+            | AstKind::Flags(_) => {}
+        }
+    }
+
+    fn render(&mut self, ast: &'a Ast) -> Option<()> {
+        if self.out.len() > MAX_APPROXIMATION_LEN {
+            return None;
+        }
+        match &ast.kind {
+            AstKind::Empty | AstKind::Assertion(_) => {}
+            AstKind::Literal(ch) => {
+                if r"\.+*?()|[]{}^$#&-~".contains(*ch) {
+                    self.out.push('\\');
+                }
+                self.out.push(*ch);
+            }
+            AstKind::Dot => self.out.push('.'),
+            AstKind::Class(_) | AstKind::Bracket(_) => self
+                .out
+                .push_str(&self.pattern[ast.span.start..ast.span.end]),
+            AstKind::Backreference(group) => {
+                let index = group - 1;
+                // Inside its own group a reference replays a partial capture,
+                // which no copy of the group describes.
+                if self.open.contains(&index) {
+                    return None;
+                }
+                let body = (*self.groups.get(index)?)?;
+                self.open.push(index);
+                self.render_group(body)?;
+                self.open.pop();
+            }
+            AstKind::Flags(_) => return None,
+            AstKind::Repetition(repetition) => {
+                self.render_group(&repetition.ast)?;
+                match repetition.max {
+                    Some(max) => self.out.push_str(&format!("{{{},{max}}}", repetition.min)),
+                    None => self.out.push_str(&format!("{{{},}}", repetition.min)),
+                }
+            }
+            AstKind::Group(group) => match group.kind {
+                GroupKind::Capture { index, .. } => {
+                    self.open.push(index);
+                    self.render_group(&group.ast)?;
+                    self.open.pop();
+                }
+                GroupKind::NonCapturing | GroupKind::Atomic => self.render_group(&group.ast)?,
+                GroupKind::Flags(_) => return None,
+                GroupKind::Lookaround { .. } => {}
+            },
+            AstKind::Concat(asts) => {
+                for ast in asts {
+                    self.render(ast)?;
+                }
+            }
+            AstKind::Alternation(branches) => {
+                self.out.push_str("(?:");
+                for (idx, branch) in branches.iter().enumerate() {
+                    if idx > 0 {
+                        self.out.push('|');
+                    }
+                    self.render(branch)?;
+                }
+                self.out.push(')');
+            }
+        }
+        Some(())
+    }
+
+    fn render_group(&mut self, ast: &'a Ast) -> Option<()> {
+        self.out.push_str("(?:");
+        self.render(ast)?;
+        self.out.push(')');
+        Some(())
+    }
+}
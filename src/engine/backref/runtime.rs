@@ -1,20 +1,44 @@
-use crate::engine::RegexMatch;
+use std::sync::{Mutex, PoisonError};
 
-use super::ast::{CaptureSpan, CharGroup, Count, Pattern};
+use anyhow::{bail, Context, Result};
+
+use crate::engine::{
+    BacktrackLimitExceeded, RegexCaptures, RegexMatch, RegexOptions, SearchBudget,
+};
+
+use super::ast::{is_digit_char, is_word_char, CaptureSpan, CharGroup, Count, Pattern};
 
 #[derive(Debug)]
 pub(super) struct CompiledBackreferenceRegex {
     plan: BackreferencePlan,
+    group_names: Vec<(String, usize)>,
 }
 
+/// The most `(pc, pos)` pairs the VM memoizes for one input. Longer inputs
+/// run without memoization.
+const MAX_VISITED_BITS: usize = 256 * 1024 * 8;
+
 #[derive(Debug)]
 struct BackreferencePlan {
-    instructions: Vec<Instruction>,
+    program: Program,
     referenced_capture_count: usize,
+    capture_program: Program,
+    capture_group_count: usize,
     start_anchor: bool,
     end_anchor: bool,
+    multiline: bool,
     search_hints: SearchHints,
     fast_path: Option<FastPath>,
+    /// Scratch space returned by finished searches, for the next one to reuse.
+    scratch: Mutex<Vec<Scratch>>,
+}
+
+#[derive(Debug)]
+struct Program {
+    instructions: Vec<Instruction>,
+    /// Whether a state at each pc may be skipped when its `(pc, pos)` was
+    /// already visited in the same search. See `memoizable_pcs`.
+    memoizable: Vec<bool>,
 }
 
 #[derive(Debug)]
@@ -28,6 +52,30 @@ struct SearchHints {
 enum FastPath {
     SingleCaptureLiteralBackref(SingleCaptureLiteralBackref),
     TwoPartReplayBackref(TwoPartReplayBackref),
+    Segments(SegmentPlan),
+}
+
+/// A pattern made only of literal strings, repeated single-character atoms,
+/// capture groups around them and backreferences to groups that already
+/// closed. It is matched by slice comparison, backtracking over repeat
+/// counts in the same order as the VM.
+#[derive(Debug)]
+struct SegmentPlan {
+    segments: Vec<Segment>,
+    capture_group_count: usize,
+}
+
+#[derive(Debug)]
+enum Segment {
+    Literal(String),
+    Repeat {
+        matcher: RepeatedAtomMatcher,
+        lazy: bool,
+    },
+    CaptureStart(usize),
+    CaptureEnd(usize),
+    /// Replays the zero-based capture group.
+    Backref(usize),
 }
 
 #[derive(Debug)]
@@ -44,14 +92,25 @@ struct TwoPartReplayBackref {
     separator: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum SimpleAtom {
     Literal(char),
     Digit,
     Word,
+    /// `.` without `--multiline-dotall`: anything but `\n`.
+    Wildcard,
+    AnyChar,
     CharGroup(CharGroup),
 }
 
+/// One character matched by `atom`, with its ASCII members precomputed as a
+/// bitset so that ASCII input is matched a byte at a time.
+#[derive(Debug, Clone)]
+struct CharClass {
+    ascii: u128,
+    atom: SimpleAtom,
+}
+
 #[derive(Debug)]
 struct AnchorLiteral {
     text: String,
@@ -75,65 +134,198 @@ enum CandidateStrategy {
     StartPredicateScan,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct RepeatedAtomMatcher {
-    atom: SimpleAtom,
+    class: CharClass,
     min: usize,
     max: Option<usize>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum Instruction {
     ConsumeLiteral(char),
-    ConsumeDigit,
-    ConsumeWord,
-    ConsumeWildcard,
-    ConsumeCharGroup(CharGroup),
-    Split { preferred: usize, fallback: usize },
+    /// A run of literal characters, fused from consecutive `ConsumeLiteral`s.
+    ConsumeString(String),
+    ConsumeClass(CharClass),
+    /// A greedy repetition of one character class. It scans as far as it can
+    /// and leaves a backtracking point for every shorter run.
+    RepeatClass(RepeatedAtomMatcher),
+    Split {
+        preferred: usize,
+        fallback: usize,
+    },
     Jump(usize),
     SaveCaptureStart(usize),
     SaveCaptureEnd(usize),
     MatchBackref(usize),
+    AssertWordBoundary,
+    AssertNotWordBoundary,
+    AssertStart,
+    AssertEnd,
+    /// Records the backtracking stack depth in a register on entry to an
+    /// atomic group.
+    MarkStack(usize),
+    /// Drops every backtracking point pushed since the matching `MarkStack`.
+    CutStack(usize),
+    /// Runs `body` as a separate program, `behind` characters back from the
+    /// current position, without consuming input. A positive lookaround
+    /// keeps the captures its body set.
+    Lookaround {
+        body: Vec<Instruction>,
+        behind: usize,
+        negated: bool,
+    },
     MatchEnd,
 }
 
-#[derive(Clone)]
+#[derive(Debug, Default)]
 struct VmState {
     pc: usize,
     pos: usize,
     captures: Vec<Option<CaptureSpan>>,
+    stack_marks: Vec<usize>,
     epsilon_trace: Vec<(usize, usize)>,
 }
 
+/// Buffers the VM reuses across runs, so forking a state does not allocate.
+#[derive(Debug, Default)]
+struct Scratch {
+    stack: Vec<VmState>,
+    free: Vec<VmState>,
+    visited: Visited,
+}
+
+/// A bitset of the `(pc, pos)` states already visited by a search, laid out
+/// by position so that later searches only clear the positions they reach.
+#[derive(Debug, Default)]
+struct Visited {
+    bits: Vec<u64>,
+    stride: usize,
+    /// Every set bit is in a word before this one.
+    dirty_end: usize,
+    active: bool,
+}
+
 #[derive(Default)]
 struct AnchorCollector {
     best: Option<(String, Option<usize>)>,
 }
 
 impl CompiledBackreferenceRegex {
-    pub(super) fn new(regex: &str) -> Self {
-        let (patterns, start_anchor, end_anchor) = Pattern::parse(regex);
-        Self {
+    pub(super) fn new(regex: &str, options: RegexOptions) -> Result<Self> {
+        let parsed = Pattern::parse(regex)?;
+        Ok(Self {
             plan: BackreferencePlan::compile(
-                &normalize_patterns(patterns),
-                start_anchor,
-                end_anchor,
-            ),
-        }
+                &normalize_patterns(parsed.patterns),
+                parsed.start_anchor,
+                parsed.end_anchor,
+                options,
+            )?,
+            group_names: parsed.group_names,
+        })
+    }
+
+    pub(super) fn find_all(
+        &self,
+        input: &str,
+        budget: &SearchBudget,
+    ) -> Result<Vec<RegexMatch>, BacktrackLimitExceeded> {
+        self.plan.find_all(input, budget)
+    }
+
+    pub(super) fn find_all_captures(
+        &self,
+        input: &str,
+        budget: &SearchBudget,
+    ) -> Result<Vec<RegexCaptures>, BacktrackLimitExceeded> {
+        self.plan.find_all_captures(input, budget)
+    }
+
+    pub(super) fn captures_len(&self) -> usize {
+        self.plan.capture_group_count + 1
+    }
+
+    /// A literal every match contains, if the pattern has one.
+    pub(super) fn required_literal(&self) -> Option<&str> {
+        let anchor = self.plan.search_hints.anchor.as_ref()?;
+        Some(&anchor.text)
     }
 
-    pub(super) fn find_all(&self, input: &str) -> Vec<RegexMatch> {
-        self.plan.find_all(input)
+    pub(super) fn capture_index(&self, name: &str) -> Option<usize> {
+        self.group_names
+            .iter()
+            .find(|(group_name, _)| group_name == name)
+            .map(|(_, idx)| idx + 1)
     }
 }
 
 impl SimpleAtom {
-    fn matches_at(&self, input: &str, pos: usize) -> bool {
+    /// The atom of a single-character pattern, ignoring its count.
+    fn from_pattern(pattern: &Pattern, dot_matches_new_line: bool) -> Option<Self> {
+        match pattern {
+            Pattern::Literal(ch, _) => Some(SimpleAtom::Literal(*ch)),
+            Pattern::Digit(_) => Some(SimpleAtom::Digit),
+            Pattern::Alphanumeric(_) => Some(SimpleAtom::Word),
+            Pattern::Wildcard(_) if dot_matches_new_line => Some(SimpleAtom::AnyChar),
+            Pattern::Wildcard(_) => Some(SimpleAtom::Wildcard),
+            Pattern::CharGroup(group, _) => Some(SimpleAtom::CharGroup(group.clone())),
+            Pattern::Alternation { .. }
+            | Pattern::CapturedGroup { .. }
+            | Pattern::AtomicGroup { .. }
+            | Pattern::Lookaround { .. }
+            | Pattern::Backreference(_)
+            | Pattern::WordBoundary
+            | Pattern::NotWordBoundary
+            | Pattern::StartAnchor
+            | Pattern::EndAnchor => None,
+        }
+    }
+
+    fn matches_char(&self, ch: char) -> bool {
         match self {
-            SimpleAtom::Literal(ch) => matches_literal(input, pos, *ch),
-            SimpleAtom::Digit => matches_digit(input, pos),
-            SimpleAtom::Word => matches_word(input, pos),
-            SimpleAtom::CharGroup(group) => matches_group(input, pos, group),
+            SimpleAtom::Literal(expected) => ch == *expected,
+            SimpleAtom::Digit => is_digit_char(ch),
+            SimpleAtom::Word => is_word_char(ch),
+            SimpleAtom::Wildcard => ch != '\n',
+            SimpleAtom::AnyChar => true,
+            SimpleAtom::CharGroup(group) => group.matches(ch),
+        }
+    }
+}
+
+impl CharClass {
+    fn new(atom: SimpleAtom) -> Self {
+        let ascii = (0..128u8)
+            .filter(|byte| atom.matches_char(char::from(*byte)))
+            .fold(0, |bits, byte| bits | 1 << byte);
+        Self { ascii, atom }
+    }
+
+    /// The position after the character at `pos`, if the class matches it.
+    fn match_at(&self, input: &str, pos: usize) -> Option<usize> {
+        let byte = *input.as_bytes().get(pos)?;
+        if byte.is_ascii() {
+            return (self.ascii & 1 << byte != 0).then_some(pos + 1);
+        }
+        let (ch, next) = current_char(input, pos)?;
+        self.atom.matches_char(ch).then_some(next)
+    }
+
+    fn matches_at(&self, input: &str, pos: usize) -> bool {
+        self.match_at(input, pos).is_some()
+    }
+
+    /// The UTF-8 width of every character the class matches, or `None` when
+    /// it also matches multi-byte characters.
+    fn byte_width(&self) -> Option<usize> {
+        match &self.atom {
+            SimpleAtom::Literal(ch) => Some(ch.len_utf8()),
+            SimpleAtom::CharGroup(group) if group.is_ascii_only() => Some(1),
+            SimpleAtom::Digit
+            | SimpleAtom::Word
+            | SimpleAtom::Wildcard
+            | SimpleAtom::AnyChar
+            | SimpleAtom::CharGroup(_) => None,
         }
     }
 }
@@ -145,9 +337,7 @@ impl StartPredicate {
             StartPredicate::Literal(ch) => matches_literal(input, pos, *ch),
             StartPredicate::Digit => matches_digit(input, pos),
             StartPredicate::Word => matches_word(input, pos),
-            StartPredicate::Wildcard => {
-                match_char(input, pos, |current| !r"\[](|)".contains(current)).is_some()
-            }
+            StartPredicate::Wildcard => current_char(input, pos).is_some(),
             StartPredicate::CharGroup(group) => matches_group(input, pos, group),
         }
     }
@@ -157,7 +347,7 @@ impl RepeatedAtomMatcher {
     fn from_pattern(pattern: &Pattern) -> Option<Self> {
         let count = pattern.count();
         let (min, max) = count.repetition_bounds();
-        if min == 0 {
+        if min == 0 || count.is_lazy() {
             return None;
         }
 
@@ -165,30 +355,46 @@ impl RepeatedAtomMatcher {
             Pattern::Literal(ch, _) => Some(SimpleAtom::Literal(*ch)),
             Pattern::Digit(_) => Some(SimpleAtom::Digit),
             Pattern::Alphanumeric(_) => Some(SimpleAtom::Word),
-            Pattern::CharGroup(group, _) => Some(SimpleAtom::CharGroup(group.clone())),
-            Pattern::Wildcard(_)
+            Pattern::CharGroup(group, _) if group.is_ascii_only() => {
+                Some(SimpleAtom::CharGroup(group.clone()))
+            }
+            Pattern::CharGroup(_, _)
+            | Pattern::Wildcard(_)
             | Pattern::Alternation { .. }
             | Pattern::CapturedGroup { .. }
-            | Pattern::Backreference(_) => None,
+            | Pattern::AtomicGroup { .. }
+            | Pattern::Lookaround { .. }
+            | Pattern::Backreference(_)
+            | Pattern::WordBoundary
+            | Pattern::NotWordBoundary
+            | Pattern::StartAnchor
+            | Pattern::EndAnchor => None,
         }?;
 
-        Some(Self { atom, min, max })
+        Some(Self {
+            class: CharClass::new(atom),
+            min,
+            max,
+        })
     }
 
     fn fixed_byte_width(&self) -> Option<usize> {
         let repetitions = self.max.filter(|max| *max == self.min)?;
-        Some(self.unit_byte_width() * repetitions)
+        Some(self.unit_byte_width()? * repetitions)
     }
 
-    fn unit_byte_width(&self) -> usize {
-        match &self.atom {
-            SimpleAtom::Literal(ch) => ch.len_utf8(),
-            SimpleAtom::Digit | SimpleAtom::Word | SimpleAtom::CharGroup(_) => 1,
-        }
+    /// The UTF-8 width of one atom, or `None` for atoms like `\d` and `\w`,
+    /// which also match multi-byte characters.
+    fn unit_byte_width(&self) -> Option<usize> {
+        self.class.byte_width()
     }
 
-    fn byte_len_for_count(&self, count: usize) -> usize {
-        self.unit_byte_width() * count
+    /// Where a run of `count` atoms ending at `end` starts.
+    fn start_for_count(&self, input: &str, end: usize, count: usize) -> Option<usize> {
+        match self.unit_byte_width() {
+            Some(width) => end.checked_sub(width * count),
+            None => (0..count).try_fold(end, |current, _| previous_char_boundary(input, current)),
+        }
     }
 
     fn matches_entire(&self, input: &str) -> bool {
@@ -196,7 +402,7 @@ impl RepeatedAtomMatcher {
         let mut count = 0;
 
         while current < input.len() {
-            if !self.atom.matches_at(input, current) {
+            if !self.class.matches_at(input, current) {
                 return false;
             }
             let Some(next) = next_char_boundary(input, current) else {
@@ -220,7 +426,7 @@ impl RepeatedAtomMatcher {
             let Some(previous) = previous_char_boundary(input, current) else {
                 break;
             };
-            if previous < scan_start || !self.atom.matches_at(input, previous) {
+            if previous < scan_start || !self.class.matches_at(input, previous) {
                 break;
             }
             count += 1;
@@ -231,86 +437,374 @@ impl RepeatedAtomMatcher {
     }
 
     fn count_forward(&self, input: &str, start: usize) -> usize {
+        self.run_forward(input, start).0
+    }
+
+    /// The number of atoms in the longest run from `start`, and its end.
+    fn run_forward(&self, input: &str, start: usize) -> (usize, usize) {
         let mut count = 0;
         let mut current = start;
 
         while self.max.is_none_or(|limit| count < limit) {
-            if !self.atom.matches_at(input, current) {
+            let Some(next) = self.class.match_at(input, current) else {
                 break;
+            };
+            count += 1;
+            current = next;
+        }
+
+        (count, current)
+    }
+
+    /// Scans the longest run from `start` for the VM. Calls `backtrack` with
+    /// the end of every shorter run of at least `min` atoms, shortest first,
+    /// and returns the end of the longest run, or `None` when even `min`
+    /// atoms do not match.
+    fn scan(&self, input: &str, start: usize, mut backtrack: impl FnMut(usize)) -> Option<usize> {
+        if let Some(width) = self.unit_byte_width() {
+            let count = self.count_forward(input, start);
+            if count < self.min {
+                return None;
             }
-            let Some(next) = next_char_boundary(input, current) else {
+            for shorter in self.min..count {
+                backtrack(start + shorter * width);
+            }
+            return Some(start + count * width);
+        }
+
+        let mut count = 0;
+        let mut current = start;
+        while self.max.is_none_or(|limit| count < limit) {
+            let Some(next) = self.class.match_at(input, current) else {
                 break;
             };
+            if count >= self.min {
+                backtrack(current);
+            }
             count += 1;
             current = next;
         }
+        (count >= self.min).then_some(current)
+    }
+}
 
-        count
+impl SegmentPlan {
+    /// Splits `patterns` into segments, or returns `None` when any part of
+    /// it needs the VM.
+    fn plan(patterns: &[Pattern], dot_matches_new_line: bool) -> Option<Self> {
+        let mut plan = Self {
+            segments: Vec::new(),
+            capture_group_count: capture_group_count(patterns),
+        };
+        let mut closed = vec![false; plan.capture_group_count];
+        plan.push_sequence(patterns, dot_matches_new_line, &mut closed)?;
+        Some(plan)
+    }
+
+    fn push_sequence(
+        &mut self,
+        patterns: &[Pattern],
+        dot_matches_new_line: bool,
+        closed: &mut [bool],
+    ) -> Option<()> {
+        for pattern in patterns {
+            match pattern {
+                Pattern::Literal(ch, Count::One) => match self.segments.last_mut() {
+                    Some(Segment::Literal(text)) => text.push(*ch),
+                    _ => self.segments.push(Segment::Literal(ch.to_string())),
+                },
+                Pattern::CapturedGroup {
+                    idx,
+                    patterns,
+                    count,
+                } if count.is_exactly_one() => {
+                    if let Some(idx) = *idx {
+                        self.segments.push(Segment::CaptureStart(idx));
+                    }
+                    self.push_sequence(patterns, dot_matches_new_line, closed)?;
+                    if let Some(idx) = *idx {
+                        self.segments.push(Segment::CaptureEnd(idx));
+                        closed[idx] = true;
+                    }
+                }
+                // A group that is still open, or not yet seen, would need the
+                // VM's handling of partial and unset captures.
+                Pattern::Backreference(group) if closed[group - 1] => {
+                    self.segments.push(Segment::Backref(group - 1));
+                }
+                _ => {
+                    let atom = SimpleAtom::from_pattern(pattern, dot_matches_new_line)?;
+                    let count = pattern.count();
+                    let (min, max) = count.repetition_bounds();
+                    self.segments.push(Segment::Repeat {
+                        matcher: RepeatedAtomMatcher {
+                            class: CharClass::new(atom),
+                            min,
+                            max,
+                        },
+                        lazy: count.is_lazy(),
+                    });
+                }
+            }
+        }
+        Some(())
     }
 }
 
 impl BackreferencePlan {
-    fn compile(patterns: &[Pattern], start_anchor: bool, end_anchor: bool) -> Self {
+    fn compile(
+        patterns: &[Pattern],
+        start_anchor: bool,
+        end_anchor: bool,
+        options: RegexOptions,
+    ) -> Result<Self> {
+        let dot_matches_new_line = options.multiline && options.dot_matches_new_line;
         let referenced_groups = referenced_groups(patterns);
         let group_slots = build_group_slots(&referenced_groups);
-        Self {
-            instructions: BackreferenceCompiler::compile(patterns, &group_slots),
+        let capture_group_count = capture_group_count(patterns);
+        if let Some(missing) = referenced_groups
+            .iter()
+            .find(|group| **group >= capture_group_count)
+        {
+            bail!(
+                "backreference \\{} refers to a missing capture group",
+                missing + 1
+            );
+        }
+        let all_group_slots = (0..capture_group_count).map(Some).collect::<Vec<_>>();
+        Ok(Self {
+            program: Program::new(BackreferenceCompiler::compile(
+                patterns,
+                &group_slots,
+                dot_matches_new_line,
+                end_anchor,
+            )?),
             referenced_capture_count: referenced_groups.len(),
+            capture_program: Program::new(BackreferenceCompiler::compile(
+                patterns,
+                &all_group_slots,
+                dot_matches_new_line,
+                end_anchor,
+            )?),
+            capture_group_count,
             start_anchor,
             end_anchor,
+            multiline: options.multiline,
             search_hints: SearchHints::analyze(patterns, start_anchor),
-            fast_path: detect_fast_path(patterns),
-        }
+            fast_path: detect_fast_path(patterns, dot_matches_new_line),
+            scratch: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Runs `search` with scratch space from the pool, and returns it after.
+    fn with_scratch<T>(&self, search: impl FnOnce(&mut Scratch) -> T) -> T {
+        let pooled = self
+            .scratch
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop();
+        let mut scratch = pooled.unwrap_or_default();
+        scratch.visited.clear();
+        let result = search(&mut scratch);
+        self.scratch
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(scratch);
+        result
+    }
+
+    /// Leftmost-first matches, iterated like `regex_automata`: an empty match
+    /// may end the input, but is skipped when it starts where the previous
+    /// match ended. Fails once `budget` runs out.
+    fn find_all(
+        &self,
+        input: &str,
+        budget: &SearchBudget,
+    ) -> Result<Vec<RegexMatch>, BacktrackLimitExceeded> {
+        self.with_scratch(|scratch| self.find_all_with(input, budget, scratch))
     }
 
-    fn find_all(&self, input: &str) -> Vec<RegexMatch> {
+    fn find_all_with(
+        &self,
+        input: &str,
+        budget: &SearchBudget,
+        scratch: &mut Scratch,
+    ) -> Result<Vec<RegexMatch>, BacktrackLimitExceeded> {
         let mut matches = Vec::new();
+        let mut last_end = None;
+        let mut keep = |found: RegexMatch, matches: &mut Vec<RegexMatch>| {
+            let overlaps = found.start == found.end && last_end == Some(found.end);
+            if !overlaps {
+                matches.push(found);
+                last_end = Some(found.end);
+            }
+            !overlaps
+        };
+
+        if self.start_anchor && self.multiline {
+            let mut from = 0;
+            while let Some(start) = line_start_at_or_after(input, from) {
+                from = match self.match_from(input, start, budget, scratch) {
+                    Some(found) if keep(found, &mut matches) && found.end > start => found.end,
+                    _ => start + 1,
+                };
+            }
+            budget.check()?;
+            return Ok(matches);
+        }
 
         if self.start_anchor {
-            if let Some(found) = self.match_from(input, 0) {
+            if let Some(found) = self.match_from(input, 0, budget, scratch) {
                 matches.push(found);
             }
-            return matches;
+            budget.check()?;
+            return Ok(matches);
         }
 
         let mut scan_start = 0;
-        while scan_start < input.len() {
-            let Some(found) = self.find_next_match(input, scan_start) else {
+        while scan_start <= input.len() {
+            let Some(found) = self.find_next_match(input, scan_start, budget, scratch) else {
                 break;
             };
-            matches.push(found);
-            scan_start = advance_after_match(input, found.start, found.end);
+            scan_start = if keep(found, &mut matches) && found.end > found.start {
+                found.end
+            } else {
+                match next_char_boundary(input, found.start) {
+                    Some(next) => next,
+                    None => break,
+                }
+            };
         }
 
-        matches
+        budget.check()?;
+        Ok(matches)
+    }
+
+    fn find_all_captures(
+        &self,
+        input: &str,
+        budget: &SearchBudget,
+    ) -> Result<Vec<RegexCaptures>, BacktrackLimitExceeded> {
+        self.with_scratch(|scratch| self.find_all_captures_with(input, budget, scratch))
+    }
+
+    fn find_all_captures_with(
+        &self,
+        input: &str,
+        budget: &SearchBudget,
+        scratch: &mut Scratch,
+    ) -> Result<Vec<RegexCaptures>, BacktrackLimitExceeded> {
+        let captures = self
+            .find_all_with(input, budget, scratch)?
+            .into_iter()
+            .map(|found| {
+                let mut groups = vec![Some(found)];
+                scratch.visited.prepare(
+                    self.capture_program.instructions.len(),
+                    input.len(),
+                    found.start,
+                );
+                match self.run(
+                    &self.capture_program,
+                    self.capture_group_count,
+                    input,
+                    found.start,
+                    budget,
+                    scratch,
+                ) {
+                    Some(state) => groups.extend(state.captures.into_iter().map(|capture| {
+                        capture.map(|span| RegexMatch {
+                            start: span.start,
+                            end: span.end,
+                        })
+                    })),
+                    None => groups.resize(self.capture_group_count + 1, None),
+                }
+                RegexCaptures::new(groups)
+            })
+            .collect();
+        budget.check()?;
+        Ok(captures)
     }
 
-    fn find_next_match(&self, input: &str, scan_start: usize) -> Option<RegexMatch> {
+    fn find_next_match(
+        &self,
+        input: &str,
+        scan_start: usize,
+        budget: &SearchBudget,
+        scratch: &mut Scratch,
+    ) -> Option<RegexMatch> {
         if self.fast_path.is_some() {
-            return self.find_fast_path_match(input, scan_start);
+            return self.find_fast_path_match(input, scan_start, budget);
         }
 
+        // Every start tried by one search shares the visited states: a state
+        // that failed from an earlier start fails from a later one too.
+        scratch
+            .visited
+            .prepare(self.program.instructions.len(), input.len(), scan_start);
         self.search_hints
             .visit_candidates(self, input, scan_start, |candidate| {
-                self.match_at(input, candidate)
+                self.match_at(input, candidate, budget, scratch)
             })
     }
 
-    fn match_from(&self, input: &str, start: usize) -> Option<RegexMatch> {
-        if self.fast_path.is_some() {
-            let found = self.find_fast_path_match(input, start)?;
-            return (found.start == start).then_some(found);
+    fn match_from(
+        &self,
+        input: &str,
+        start: usize,
+        budget: &SearchBudget,
+        scratch: &mut Scratch,
+    ) -> Option<RegexMatch> {
+        match &self.fast_path {
+            Some(FastPath::Segments(plan)) => {
+                let mut captures = vec![None; plan.capture_group_count];
+                return self.match_segments_from(plan, input, start, budget, &mut captures);
+            }
+            Some(_) => {
+                let found = self.find_fast_path_match(input, start, budget)?;
+                return (found.start == start).then_some(found);
+            }
+            None => {}
         }
 
-        self.match_at(input, start)
+        scratch
+            .visited
+            .prepare(self.program.instructions.len(), input.len(), start);
+        self.match_at(input, start, budget, scratch)
     }
 
-    fn match_at(&self, input: &str, start: usize) -> Option<RegexMatch> {
-        let end = self.execute(input, start)?;
-        (!self.end_anchor || end == input.len()).then_some(RegexMatch { start, end })
+    fn match_at(
+        &self,
+        input: &str,
+        start: usize,
+        budget: &SearchBudget,
+        scratch: &mut Scratch,
+    ) -> Option<RegexMatch> {
+        let end = self.execute(input, start, budget, scratch)?;
+        Some(RegexMatch { start, end })
+    }
+
+    fn accepts_end(&self, input: &str, end: usize) -> bool {
+        !self.end_anchor || self.is_line_end(input, end)
     }
 
-    fn find_fast_path_match(&self, input: &str, scan_start: usize) -> Option<RegexMatch> {
+    /// Whether `^` matches at `pos`.
+    fn is_line_start(&self, input: &str, pos: usize) -> bool {
+        pos == 0 || (self.multiline && input.as_bytes()[pos - 1] == b'\n')
+    }
+
+    /// Whether `$` matches at `pos`.
+    fn is_line_end(&self, input: &str, pos: usize) -> bool {
+        pos == input.len() || (self.multiline && input.as_bytes()[pos] == b'\n')
+    }
+
+    fn find_fast_path_match(
+        &self,
+        input: &str,
+        scan_start: usize,
+        budget: &SearchBudget,
+    ) -> Option<RegexMatch> {
         let fast_path = self.fast_path.as_ref()?;
         match fast_path {
             FastPath::SingleCaptureLiteralBackref(fast_path) => {
@@ -319,6 +813,108 @@ impl BackreferencePlan {
             FastPath::TwoPartReplayBackref(fast_path) => {
                 self.find_two_part_replay_backref(input, scan_start, fast_path)
             }
+            FastPath::Segments(plan) => {
+                let mut captures = vec![None; plan.capture_group_count];
+                self.search_hints
+                    .visit_candidates(self, input, scan_start, |start| {
+                        self.match_segments_from(plan, input, start, budget, &mut captures)
+                    })
+            }
+        }
+    }
+
+    fn match_segments_from(
+        &self,
+        plan: &SegmentPlan,
+        input: &str,
+        start: usize,
+        budget: &SearchBudget,
+        captures: &mut [Option<CaptureSpan>],
+    ) -> Option<RegexMatch> {
+        let end = self.match_segments(&plan.segments, input, start, budget, captures)?;
+        Some(RegexMatch { start, end })
+    }
+
+    /// Matches `segments` at `pos` and returns the end of the match. A repeat
+    /// tries its longest run first, or its shortest when lazy, and gives back
+    /// one atom at a time when the rest fails to match.
+    fn match_segments(
+        &self,
+        segments: &[Segment],
+        input: &str,
+        pos: usize,
+        budget: &SearchBudget,
+        captures: &mut [Option<CaptureSpan>],
+    ) -> Option<usize> {
+        let Some((segment, rest)) = segments.split_first() else {
+            return self.accepts_end(input, pos).then_some(pos);
+        };
+        if !budget.step() {
+            return None;
+        }
+
+        match segment {
+            Segment::Literal(text) => {
+                let end = pos + text.len();
+                (input.get(pos..end)? == text.as_str())
+                    .then(|| self.match_segments(rest, input, end, budget, captures))?
+            }
+            Segment::Backref(group) => {
+                let span = captures[*group]?;
+                let end = pos + (span.end - span.start);
+                (input.get(pos..end)? == &input[span.start..span.end])
+                    .then(|| self.match_segments(rest, input, end, budget, captures))?
+            }
+            Segment::CaptureStart(group) => {
+                captures[*group] = Some(CaptureSpan {
+                    start: pos,
+                    end: pos,
+                });
+                self.match_segments(rest, input, pos, budget, captures)
+            }
+            Segment::CaptureEnd(group) => {
+                if let Some(span) = &mut captures[*group] {
+                    span.end = pos;
+                }
+                self.match_segments(rest, input, pos, budget, captures)
+            }
+            Segment::Repeat {
+                matcher,
+                lazy: false,
+            } => {
+                let (mut count, mut end) = matcher.run_forward(input, pos);
+                while count >= matcher.min {
+                    if let Some(found) = self.match_segments(rest, input, end, budget, captures) {
+                        return Some(found);
+                    }
+                    if count == matcher.min {
+                        break;
+                    }
+                    end = previous_char_boundary(input, end)?;
+                    count -= 1;
+                }
+                None
+            }
+            Segment::Repeat {
+                matcher,
+                lazy: true,
+            } => {
+                let mut count = 0;
+                let mut end = pos;
+                loop {
+                    if count >= matcher.min {
+                        if let Some(found) = self.match_segments(rest, input, end, budget, captures)
+                        {
+                            return Some(found);
+                        }
+                    }
+                    if matcher.max.is_some_and(|max| count >= max) {
+                        return None;
+                    }
+                    end = matcher.class.match_at(input, end)?;
+                    count += 1;
+                }
+            }
         }
     }
 
@@ -374,7 +970,7 @@ impl BackreferencePlan {
             }
             let found = (input.get(separator_end..end) == Some(capture))
                 .then_some(RegexMatch { start, end })?;
-            return (!self.end_anchor || found.end == input.len()).then_some(found);
+            return self.accepts_end(input, found.end).then_some(found);
         }
 
         let left_count = matcher.count_backward(input, scan_start, separator_start);
@@ -386,15 +982,13 @@ impl BackreferencePlan {
         }
 
         let upper = matcher.max.unwrap_or(max_count).min(max_count);
-        let unit_width = matcher.unit_byte_width();
         for count in (matcher.min..=upper).rev() {
-            let byte_len = count * unit_width;
-            let start = separator_start.checked_sub(byte_len)?;
-            let end = separator_end.checked_add(byte_len)?;
+            let start = matcher.start_for_count(input, separator_start, count)?;
+            let end = separator_end.checked_add(separator_start - start)?;
             let capture = input.get(start..separator_start)?;
             if input.get(separator_end..end) == Some(capture) {
                 let found = RegexMatch { start, end };
-                if !self.end_anchor || found.end == input.len() {
+                if self.accepts_end(input, found.end) {
                     return Some(found);
                 }
             }
@@ -458,8 +1052,11 @@ impl BackreferencePlan {
         }
 
         for second_count in (fast_path.second_matcher.min..=second_upper).rev() {
-            let second_byte_len = fast_path.second_matcher.byte_len_for_count(second_count);
-            let Some(second_start) = separator_start.checked_sub(second_byte_len) else {
+            let Some(second_start) =
+                fast_path
+                    .second_matcher
+                    .start_for_count(input, separator_start, second_count)
+            else {
                 continue;
             };
             let Some(first_end) = second_start.checked_sub(fast_path.middle_separator.len()) else {
@@ -483,8 +1080,11 @@ impl BackreferencePlan {
             }
 
             for first_count in (fast_path.first_matcher.min..=first_upper).rev() {
-                let first_byte_len = fast_path.first_matcher.byte_len_for_count(first_count);
-                let Some(start) = first_end.checked_sub(first_byte_len) else {
+                let Some(start) =
+                    fast_path
+                        .first_matcher
+                        .start_for_count(input, first_end, first_count)
+                else {
                     continue;
                 };
                 if start < scan_start || !input.is_char_boundary(start) {
@@ -499,7 +1099,7 @@ impl BackreferencePlan {
                 };
                 let found = (input.get(separator_end..end) == Some(left_side))
                     .then_some(RegexMatch { start, end })?;
-                if !self.end_anchor || found.end == input.len() {
+                if self.accepts_end(input, found.end) {
                     return Some(found);
                 }
             }
@@ -508,18 +1108,96 @@ impl BackreferencePlan {
         None
     }
 
-    fn execute(&self, input: &str, start: usize) -> Option<usize> {
-        let mut stack = vec![VmState::new(self.referenced_capture_count, start)];
+    fn execute(
+        &self,
+        input: &str,
+        start: usize,
+        budget: &SearchBudget,
+        scratch: &mut Scratch,
+    ) -> Option<usize> {
+        self.run(
+            &self.program,
+            self.referenced_capture_count,
+            input,
+            start,
+            budget,
+            scratch,
+        )
+        .map(|state| state.pos)
+    }
+
+    /// Runs `program` from `start`, memoizing visited states when
+    /// `scratch.visited` was prepared for it.
+    fn run(
+        &self,
+        program: &Program,
+        capture_count: usize,
+        input: &str,
+        start: usize,
+        budget: &SearchBudget,
+        scratch: &mut Scratch,
+    ) -> Option<VmState> {
+        let initial = scratch.state(capture_count, start);
+        let memoizable = scratch
+            .visited
+            .active
+            .then_some(program.memoizable.as_slice());
+        self.run_from(
+            &program.instructions,
+            memoizable,
+            input,
+            initial,
+            budget,
+            scratch,
+        )
+    }
 
-        while let Some(mut state) = stack.pop() {
+    /// Runs `instructions` from `initial` on top of the states already on
+    /// `scratch.stack`, giving up without a match once `budget` runs out.
+    fn run_from(
+        &self,
+        instructions: &[Instruction],
+        memoizable: Option<&[bool]>,
+        input: &str,
+        initial: VmState,
+        budget: &SearchBudget,
+        scratch: &mut Scratch,
+    ) -> Option<VmState> {
+        let base = scratch.stack.len();
+        scratch.stack.push(initial);
+        let found = self.run_threads(instructions, memoizable, input, base, budget, scratch);
+        scratch.cut(base);
+        found
+    }
+
+    fn run_threads(
+        &self,
+        instructions: &[Instruction],
+        memoizable: Option<&[bool]>,
+        input: &str,
+        base: usize,
+        budget: &SearchBudget,
+        scratch: &mut Scratch,
+    ) -> Option<VmState> {
+        while scratch.stack.len() > base {
+            let mut state = scratch.stack.pop()?;
             loop {
-                let instruction = self.instructions.get(state.pc)?;
-
-                if instruction.is_epsilon() && state.has_visited_epsilon() {
-                    break;
+                if !budget.step() {
+                    return None;
                 }
-
-                if instruction.is_epsilon() {
+                let instruction = instructions.get(state.pc)?;
+
+                // A memoized state is dropped on its second visit. That also
+                // stops empty loops, which other states detect from their
+                // own trace of epsilon steps since the last consumed input.
+                if memoizable.is_some_and(|memoizable| memoizable[state.pc]) {
+                    if !scratch.visited.insert(state.pc, state.pos) {
+                        break;
+                    }
+                } else if instruction.is_epsilon() {
+                    if state.has_visited_epsilon() {
+                        break;
+                    }
                     state.mark_epsilon();
                 }
 
@@ -531,43 +1209,52 @@ impl BackreferencePlan {
                         };
                         state.advance(next);
                     }
-                    Instruction::ConsumeDigit => {
-                        let Some(next) =
-                            match_char(input, state.pos, |current| current.is_ascii_digit())
-                        else {
+                    Instruction::ConsumeString(text) => {
+                        if !input
+                            .get(state.pos..)
+                            .is_some_and(|rest| rest.starts_with(text.as_str()))
+                        {
                             break;
-                        };
-                        state.advance(next);
-                    }
-                    Instruction::ConsumeWord => {
-                        let Some(next) = match_char(input, state.pos, |current| {
-                            current.is_ascii_alphanumeric() || current == '_'
-                        }) else {
-                            break;
-                        };
-                        state.advance(next);
+                        }
+                        state.advance(state.pos + text.len());
                     }
-                    Instruction::ConsumeWildcard => {
-                        let Some(next) =
-                            match_char(input, state.pos, |current| !r"\[](|)".contains(current))
-                        else {
+                    Instruction::ConsumeClass(class) => {
+                        let Some(next) = class.match_at(input, state.pos) else {
                             break;
                         };
                         state.advance(next);
                     }
-                    Instruction::ConsumeCharGroup(group) => {
-                        let Some(next) =
-                            match_char(input, state.pos, |current| group.matches(current))
-                        else {
+                    Instruction::RepeatClass(repeat) => {
+                        let next_pc = state.pc + 1;
+                        let memoized = memoizable.is_some_and(|memoizable| memoizable[next_pc]);
+                        let end = repeat.scan(input, state.pos, |pos| {
+                            if memoized && scratch.visited.contains(next_pc, pos) {
+                                return;
+                            }
+                            let alternate = if pos == state.pos {
+                                scratch.fork(&state, next_pc)
+                            } else {
+                                let mut alternate = scratch.fork(&state, state.pc);
+                                alternate.advance(pos);
+                                alternate
+                            };
+                            scratch.stack.push(alternate);
+                        });
+                        let Some(end) = end else {
                             break;
                         };
-                        state.advance(next);
+                        if end == state.pos {
+                            state.pc = next_pc;
+                        } else {
+                            state.advance(end);
+                        }
                     }
                     Instruction::Split {
                         preferred,
                         fallback,
                     } => {
-                        stack.push(state.fork(*fallback));
+                        let alternate = scratch.fork(&state, *fallback);
+                        scratch.stack.push(alternate);
                         state.pc = *preferred;
                     }
                     Instruction::Jump(target) => state.pc = *target,
@@ -603,9 +1290,65 @@ impl BackreferencePlan {
                             state.epsilon_trace.clear();
                         }
                     }
-                    Instruction::MatchEnd => return Some(state.pos),
+                    Instruction::AssertWordBoundary | Instruction::AssertNotWordBoundary => {
+                        let expected = matches!(instruction, Instruction::AssertWordBoundary);
+                        if is_word_boundary(input, state.pos) != expected {
+                            break;
+                        }
+                        state.pc += 1;
+                    }
+                    Instruction::AssertStart => {
+                        if !self.is_line_start(input, state.pos) {
+                            break;
+                        }
+                        state.pc += 1;
+                    }
+                    Instruction::AssertEnd => {
+                        if !self.is_line_end(input, state.pos) {
+                            break;
+                        }
+                        state.pc += 1;
+                    }
+                    Instruction::MarkStack(register) => {
+                        if state.stack_marks.len() <= *register {
+                            state.stack_marks.resize(register + 1, 0);
+                        }
+                        state.stack_marks[*register] = scratch.stack.len();
+                        state.pc += 1;
+                    }
+                    Instruction::CutStack(register) => {
+                        scratch.cut(state.stack_marks[*register]);
+                        state.pc += 1;
+                    }
+                    Instruction::Lookaround {
+                        body,
+                        behind,
+                        negated,
+                    } => {
+                        let probe = (0..*behind)
+                            .try_fold(state.pos, |pos, _| previous_char_boundary(input, pos))
+                            .and_then(|start| {
+                                let probe = scratch.probe(&state, start);
+                                self.run_from(body, None, input, probe, budget, scratch)
+                            });
+                        match probe {
+                            Some(probe) if *negated => {
+                                scratch.free.push(probe);
+                                break;
+                            }
+                            Some(mut probe) => {
+                                std::mem::swap(&mut state.captures, &mut probe.captures);
+                                scratch.free.push(probe);
+                            }
+                            None if *negated => {}
+                            None => break,
+                        }
+                        state.pc += 1;
+                    }
+                    Instruction::MatchEnd => return Some(state),
                 }
             }
+            scratch.free.push(state);
         }
 
         None
@@ -683,82 +1426,166 @@ impl SearchHints {
 }
 
 impl BackreferenceCompiler<'_> {
-    fn compile(patterns: &[Pattern], group_slots: &[Option<usize>]) -> Vec<Instruction> {
+    fn compile(
+        patterns: &[Pattern],
+        group_slots: &[Option<usize>],
+        dot_matches_new_line: bool,
+        end_anchor: bool,
+    ) -> Result<Vec<Instruction>> {
         let mut compiler = BackreferenceCompiler {
             instructions: Vec::new(),
+            stack_registers: 0,
             group_slots,
+            dot_matches_new_line,
         };
-        compiler.compile_sequence(patterns);
+        compiler.compile_sequence(patterns)?;
+        // A lifted `$` is checked before the match is accepted, so that
+        // failing it backtracks like any other assertion.
+        if end_anchor {
+            compiler.instructions.push(Instruction::AssertEnd);
+        }
         compiler.instructions.push(Instruction::MatchEnd);
-        compiler.instructions
+        Ok(compiler.instructions)
     }
 
-    fn compile_sequence(&mut self, patterns: &[Pattern]) {
-        for pattern in patterns {
-            self.compile_pattern(pattern);
+    fn compile_sequence(&mut self, patterns: &[Pattern]) -> Result<()> {
+        let mut rest = patterns;
+        while let Some((pattern, tail)) = rest.split_first() {
+            let run = rest
+                .iter()
+                .map_while(|pattern| match pattern {
+                    Pattern::Literal(ch, Count::One) => Some(*ch),
+                    _ => None,
+                })
+                .collect::<String>();
+            let run_len = run.chars().count();
+            if run_len > 1 {
+                self.emit(Instruction::ConsumeString(run));
+                rest = &rest[run_len..];
+            } else {
+                self.compile_pattern(pattern)?;
+                rest = tail;
+            }
         }
+        Ok(())
     }
 
-    fn compile_pattern(&mut self, pattern: &Pattern) {
+    fn compile_pattern(&mut self, pattern: &Pattern) -> Result<()> {
         match pattern {
-            Pattern::Literal(ch, count) => {
-                self.compile_count(*count, |compiler| {
-                    compiler.emit(Instruction::ConsumeLiteral(*ch));
-                });
+            Pattern::Literal(..)
+            | Pattern::Digit(_)
+            | Pattern::Alphanumeric(_)
+            | Pattern::Wildcard(_)
+            | Pattern::CharGroup(..) => {
+                let count = pattern.count();
+                let atom = SimpleAtom::from_pattern(pattern, self.dot_matches_new_line)
+                    .expect("single-character patterns have an atom");
+                if !matches!(count, Count::One | Count::Lazy { .. }) {
+                    let (min, max) = count.repetition_bounds();
+                    self.emit(Instruction::RepeatClass(RepeatedAtomMatcher {
+                        class: CharClass::new(atom),
+                        min,
+                        max,
+                    }));
+                    return Ok(());
+                }
+                let consume = match atom {
+                    SimpleAtom::Literal(ch) => Instruction::ConsumeLiteral(ch),
+                    atom => Instruction::ConsumeClass(CharClass::new(atom)),
+                };
+                self.compile_count(count, |compiler| {
+                    compiler.emit(consume.clone());
+                    Ok(())
+                })
             }
-            Pattern::Digit(count) => {
-                self.compile_count(*count, |compiler| compiler.emit(Instruction::ConsumeDigit));
+            Pattern::WordBoundary => {
+                self.emit(Instruction::AssertWordBoundary);
+                Ok(())
             }
-            Pattern::Alphanumeric(count) => {
-                self.compile_count(*count, |compiler| compiler.emit(Instruction::ConsumeWord));
+            Pattern::NotWordBoundary => {
+                self.emit(Instruction::AssertNotWordBoundary);
+                Ok(())
             }
-            Pattern::Wildcard(count) => {
-                self.compile_count(*count, |compiler| {
-                    compiler.emit(Instruction::ConsumeWildcard);
-                });
+            Pattern::StartAnchor => {
+                self.emit(Instruction::AssertStart);
+                Ok(())
             }
-            Pattern::CharGroup(group, count) => {
-                let group = group.clone();
-                self.compile_count(*count, move |compiler| {
-                    compiler.emit(Instruction::ConsumeCharGroup(group.clone()));
-                });
+            Pattern::EndAnchor => {
+                self.emit(Instruction::AssertEnd);
+                Ok(())
             }
             Pattern::Backreference(group) => {
                 let slot = self.group_slots[*group - 1]
                     .unwrap_or_else(|| panic!("missing slot for referenced capture group {group}"));
                 self.emit(Instruction::MatchBackref(slot));
+                Ok(())
             }
             Pattern::CapturedGroup {
                 idx,
                 patterns,
                 count,
             } => {
-                let slot = self.group_slots.get(*idx).copied().flatten();
+                let slot = idx.and_then(|idx| self.group_slots.get(idx).copied().flatten());
                 self.compile_count(*count, |compiler| {
                     if let Some(slot) = slot {
                         compiler.emit(Instruction::SaveCaptureStart(slot));
                     }
-                    compiler.compile_sequence(patterns);
+                    compiler.compile_sequence(patterns)?;
                     if let Some(slot) = slot {
                         compiler.emit(Instruction::SaveCaptureEnd(slot));
                     }
-                });
+                    Ok(())
+                })
             }
             Pattern::Alternation {
                 idx,
                 alternatives,
                 count,
             } => {
-                let slot = self.group_slots.get(*idx).copied().flatten();
+                let slot = idx.and_then(|idx| self.group_slots.get(idx).copied().flatten());
                 self.compile_count(*count, |compiler| {
                     if let Some(slot) = slot {
                         compiler.emit(Instruction::SaveCaptureStart(slot));
                     }
-                    compiler.compile_alternatives(alternatives);
+                    compiler.compile_alternatives(alternatives)?;
                     if let Some(slot) = slot {
                         compiler.emit(Instruction::SaveCaptureEnd(slot));
                     }
+                    Ok(())
+                })
+            }
+            Pattern::AtomicGroup { patterns, count } => {
+                let register = self.stack_registers;
+                self.stack_registers += 1;
+                self.compile_count(*count, |compiler| {
+                    compiler.emit(Instruction::MarkStack(register));
+                    compiler.compile_sequence(patterns)?;
+                    compiler.emit(Instruction::CutStack(register));
+                    Ok(())
+                })
+            }
+            Pattern::Lookaround {
+                ahead,
+                negated,
+                patterns,
+            } => {
+                let behind = if *ahead {
+                    0
+                } else {
+                    sequence_fixed_width(patterns, WidthUnit::Chars)
+                        .context("lookbehind requires a fixed-width body")?
+                };
+                let outer = std::mem::take(&mut self.instructions);
+                let compiled = self.compile_sequence(patterns);
+                self.instructions.push(Instruction::MatchEnd);
+                let body = std::mem::replace(&mut self.instructions, outer);
+                compiled?;
+                self.emit(Instruction::Lookaround {
+                    body,
+                    behind,
+                    negated: *negated,
                 });
+                Ok(())
             }
         }
     }
@@ -766,73 +1593,108 @@ impl BackreferenceCompiler<'_> {
     fn compile_count(
         &mut self,
         count: Count,
-        mut emit_body: impl FnMut(&mut BackreferenceCompiler<'_>),
-    ) {
+        mut emit_body: impl FnMut(&mut BackreferenceCompiler<'_>) -> Result<()>,
+    ) -> Result<()> {
         match count {
-            Count::One => emit_body(self),
-            Count::ZeroOrOne => self.compile_optional(&mut emit_body),
-            Count::OneOrMore => self.compile_one_or_more(&mut emit_body),
-            Count::ZeroOrMore => self.compile_zero_or_more(&mut emit_body),
+            Count::One => emit_body(self)?,
+            Count::ZeroOrOne => self.compile_optional(&mut emit_body, true)?,
+            Count::OneOrMore => self.compile_one_or_more(&mut emit_body)?,
+            Count::ZeroOrMore => self.compile_zero_or_more(&mut emit_body, true)?,
             Count::Exact(times) => {
                 for _ in 0..times {
-                    emit_body(self);
+                    emit_body(self)?;
                 }
             }
             Count::AtLeast(min) => {
                 for _ in 0..min {
-                    emit_body(self);
+                    emit_body(self)?;
                 }
-                self.compile_zero_or_more(&mut emit_body);
+                self.compile_zero_or_more(&mut emit_body, true)?;
             }
             Count::Range(min, max) => {
                 for _ in 0..min {
-                    emit_body(self);
+                    emit_body(self)?;
                 }
                 for _ in min..max {
-                    self.compile_optional(&mut emit_body);
+                    self.compile_optional(&mut emit_body, true)?;
+                }
+            }
+            Count::Lazy { min, max } => {
+                for _ in 0..min {
+                    emit_body(self)?;
+                }
+                match max {
+                    Some(max) => {
+                        for _ in min..max {
+                            self.compile_optional(&mut emit_body, false)?;
+                        }
+                    }
+                    None => self.compile_zero_or_more(&mut emit_body, false)?,
                 }
             }
         }
+        Ok(())
     }
 
-    fn compile_optional(&mut self, emit_body: &mut impl FnMut(&mut BackreferenceCompiler<'_>)) {
+    /// Emits an optional body. A greedy split prefers entering the body; a
+    /// lazy one prefers skipping it.
+    fn compile_optional(
+        &mut self,
+        emit_body: &mut impl FnMut(&mut BackreferenceCompiler<'_>) -> Result<()>,
+        greedy: bool,
+    ) -> Result<()> {
         let split_idx = self.emit_split_placeholder();
         let body_start = self.instructions.len();
-        emit_body(self);
-        self.patch_split(split_idx, body_start, self.instructions.len());
+        emit_body(self)?;
+        self.patch_repeat_split(split_idx, body_start, self.instructions.len(), greedy);
+        Ok(())
     }
 
-    fn compile_one_or_more(&mut self, emit_body: &mut impl FnMut(&mut BackreferenceCompiler<'_>)) {
+    fn compile_one_or_more(
+        &mut self,
+        emit_body: &mut impl FnMut(&mut BackreferenceCompiler<'_>) -> Result<()>,
+    ) -> Result<()> {
         let body_start = self.instructions.len();
-        emit_body(self);
+        emit_body(self)?;
         let split_idx = self.emit_split_placeholder();
         self.patch_split(split_idx, body_start, self.instructions.len());
+        Ok(())
     }
 
-    fn compile_zero_or_more(&mut self, emit_body: &mut impl FnMut(&mut BackreferenceCompiler<'_>)) {
-        let split_idx = self.emit_split_placeholder();
+    /// Emits `x*` as `(x+)?`. When `x` can match empty, an iteration that
+    /// does so keeps its captures instead of being cut off by the loop
+    /// check, as in `regex_automata`.
+    fn compile_zero_or_more(
+        &mut self,
+        emit_body: &mut impl FnMut(&mut BackreferenceCompiler<'_>) -> Result<()>,
+        greedy: bool,
+    ) -> Result<()> {
+        let enter_idx = self.emit_split_placeholder();
         let body_start = self.instructions.len();
-        emit_body(self);
-        self.emit(Instruction::Jump(split_idx));
-        self.patch_split(split_idx, body_start, self.instructions.len());
+        emit_body(self)?;
+        let repeat_idx = self.emit_split_placeholder();
+        let exit = self.instructions.len();
+        self.patch_repeat_split(enter_idx, body_start, exit, greedy);
+        self.patch_repeat_split(repeat_idx, body_start, exit, greedy);
+        Ok(())
     }
 
-    fn compile_alternatives(&mut self, alternatives: &[Vec<Pattern>]) {
+    fn compile_alternatives(&mut self, alternatives: &[Vec<Pattern>]) -> Result<()> {
         if let Some((first, remaining)) = alternatives.split_first() {
             if remaining.is_empty() {
-                self.compile_sequence(first);
-                return;
+                return self.compile_sequence(first);
             }
 
             let split_idx = self.emit_split_placeholder();
             let first_start = self.instructions.len();
-            self.compile_sequence(first);
+            self.compile_sequence(first)?;
             let jump_idx = self.emit_jump_placeholder();
             let fallback_start = self.instructions.len();
-            self.compile_alternatives(remaining);
+            self.compile_alternatives(remaining)?;
             self.patch_split(split_idx, first_start, fallback_start);
             self.patch_jump(jump_idx, self.instructions.len());
         }
+        Ok(())
     }
 
     fn emit(&mut self, instruction: Instruction) {
@@ -861,6 +1723,14 @@ impl BackreferenceCompiler<'_> {
         };
     }
 
+    fn patch_repeat_split(&mut self, idx: usize, body: usize, skip: usize, greedy: bool) {
+        if greedy {
+            self.patch_split(idx, body, skip);
+        } else {
+            self.patch_split(idx, skip, body);
+        }
+    }
+
     fn patch_jump(&mut self, idx: usize, target: usize) {
         self.instructions[idx] = Instruction::Jump(target);
     }
@@ -883,7 +1753,7 @@ impl AnchorCollector {
                 }
                 current_run.extend(std::iter::repeat_n(ch, repetitions));
                 self.consider_run(&current_run, run_prefix);
-                current_prefix = combine_widths(current_prefix, Some(repetitions));
+                current_prefix = combine_widths(current_prefix, Some(ch.len_utf8() * repetitions));
                 continue;
             }
 
@@ -891,14 +1761,15 @@ impl AnchorCollector {
 
             if let Pattern::CapturedGroup {
                 patterns, count, ..
-            } = pattern
+            }
+            | Pattern::AtomicGroup { patterns, count } = pattern
             {
                 if count.is_exactly_one() {
                     self.visit_sequence(patterns, current_prefix);
                 }
             }
 
-            current_prefix = combine_widths(current_prefix, fixed_width(pattern));
+            current_prefix = combine_widths(current_prefix, fixed_width(pattern, WidthUnit::Bytes));
         }
 
         current_prefix
@@ -916,7 +1787,52 @@ impl AnchorCollector {
     }
 }
 
+impl Program {
+    fn new(instructions: Vec<Instruction>) -> Self {
+        Self {
+            memoizable: memoizable_pcs(&instructions),
+            instructions,
+        }
+    }
+}
+
 impl Instruction {
+    fn successors(&self, pc: usize) -> [Option<usize>; 2] {
+        match self {
+            Instruction::Split {
+                preferred,
+                fallback,
+            } => [Some(*preferred), Some(*fallback)],
+            Instruction::Jump(target) => [Some(*target), None],
+            Instruction::MatchEnd => [None, None],
+            _ => [Some(pc + 1), None],
+        }
+    }
+
+    /// Adds the capture slots whose values this instruction's outcome
+    /// depends on to `slots`.
+    fn read_slots(&self, slots: &mut Vec<usize>) {
+        match self {
+            Instruction::MatchBackref(slot) => slots.push(*slot),
+            Instruction::Lookaround { body, .. } => {
+                for instruction in body {
+                    instruction.read_slots(slots);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether every way through this instruction consumes input.
+    fn always_consumes(&self) -> bool {
+        match self {
+            Instruction::ConsumeLiteral(_) | Instruction::ConsumeClass(_) => true,
+            Instruction::ConsumeString(text) => !text.is_empty(),
+            Instruction::RepeatClass(repeat) => repeat.min > 0,
+            _ => false,
+        }
+    }
+
     fn is_epsilon(&self) -> bool {
         matches!(
             self,
@@ -925,21 +1841,19 @@ impl Instruction {
                 | Instruction::SaveCaptureStart(_)
                 | Instruction::SaveCaptureEnd(_)
                 | Instruction::MatchBackref(_)
+                | Instruction::AssertWordBoundary
+                | Instruction::AssertNotWordBoundary
+                | Instruction::AssertStart
+                | Instruction::AssertEnd
+                | Instruction::MarkStack(_)
+                | Instruction::CutStack(_)
+                | Instruction::Lookaround { .. }
                 | Instruction::MatchEnd
         )
     }
 }
 
 impl VmState {
-    fn new(referenced_capture_count: usize, start: usize) -> Self {
-        Self {
-            pc: 0,
-            pos: start,
-            captures: vec![None; referenced_capture_count],
-            epsilon_trace: Vec::new(),
-        }
-    }
-
     fn has_visited_epsilon(&self) -> bool {
         self.epsilon_trace
             .iter()
@@ -955,17 +1869,184 @@ impl VmState {
         self.pc += 1;
         self.epsilon_trace.clear();
     }
+}
 
-    fn fork(&self, pc: usize) -> Self {
-        let mut alternate = self.clone();
+impl Scratch {
+    /// A state at `start` with no captures, reusing a freed state's buffers.
+    fn state(&mut self, capture_count: usize, start: usize) -> VmState {
+        let mut state = self.free.pop().unwrap_or_default();
+        state.pc = 0;
+        state.pos = start;
+        state.captures.clear();
+        state.captures.resize(capture_count, None);
+        state.stack_marks.clear();
+        state.epsilon_trace.clear();
+        state
+    }
+
+    /// A copy of `state` that resumes at `pc`.
+    fn fork(&mut self, state: &VmState, pc: usize) -> VmState {
+        let mut alternate = self.free.pop().unwrap_or_default();
         alternate.pc = pc;
+        alternate.pos = state.pos;
+        alternate.captures.clone_from(&state.captures);
+        alternate.stack_marks.clone_from(&state.stack_marks);
+        alternate.epsilon_trace.clone_from(&state.epsilon_trace);
         alternate
     }
+
+    /// A state that starts a lookaround body at `pos` with the captures of
+    /// `state`.
+    fn probe(&mut self, state: &VmState, pos: usize) -> VmState {
+        let mut probe = self.state(0, pos);
+        probe.captures.clone_from(&state.captures);
+        probe
+    }
+
+    /// Drops every state on the stack above `depth`.
+    fn cut(&mut self, depth: usize) {
+        self.free.extend(self.stack.drain(depth..));
+    }
+}
+
+impl Visited {
+    fn clear(&mut self) {
+        self.bits[..self.dirty_end].fill(0);
+        self.dirty_end = 0;
+    }
+
+    /// Gets ready for a search from `from` with a program of `stride`
+    /// instructions, forgetting states at `from` or later. Memoization stays
+    /// off when the bitset would grow past `MAX_VISITED_BITS`.
+    fn prepare(&mut self, stride: usize, input_len: usize, from: usize) {
+        let len = stride * (input_len + 1);
+        self.active = len <= MAX_VISITED_BITS;
+        if !self.active {
+            return;
+        }
+        if stride == self.stride {
+            let first = (from * stride / 64).min(self.dirty_end);
+            self.bits[first..self.dirty_end].fill(0);
+            self.dirty_end = first;
+        } else {
+            self.clear();
+            self.stride = stride;
+        }
+        let words = len.div_ceil(64);
+        if self.bits.len() < words {
+            self.bits.resize(words, 0);
+        }
+    }
+
+    fn contains(&self, pc: usize, pos: usize) -> bool {
+        let bit = pos * self.stride + pc;
+        self.bits[bit / 64] & 1 << (bit % 64) != 0
+    }
+
+    /// Marks `(pc, pos)` visited, and returns whether it was not already.
+    fn insert(&mut self, pc: usize, pos: usize) -> bool {
+        let bit = pos * self.stride + pc;
+        let (word, mask) = (bit / 64, 1 << (bit % 64));
+        let fresh = self.bits[word] & mask == 0;
+        self.bits[word] |= mask;
+        self.dirty_end = self.dirty_end.max(word + 1);
+        fresh
+    }
 }
 
 struct BackreferenceCompiler<'a> {
     instructions: Vec<Instruction>,
+    /// Registers allocated so far for atomic groups' stack depths.
+    stack_registers: usize,
     group_slots: &'a [Option<usize>],
+    dot_matches_new_line: bool,
+}
+
+/// The pcs whose states fail or succeed by `(pc, pos)` alone, so the VM can
+/// skip a state it has already visited. That rules out pcs with a path to a
+/// backreference that reads a capture before setting it again, and pcs inside
+/// an atomic group, where which exit the group takes depends on the states
+/// tried before. It also rules out every pc on a cycle that can run without
+/// consuming input and through one of those pcs: such a cycle is cut by the
+/// state's own trace of epsilon steps, which `(pc, pos)` does not capture.
+fn memoizable_pcs(instructions: &[Instruction]) -> Vec<bool> {
+    let mut memoizable = vec![true; instructions.len()];
+    let mut predecessors = vec![Vec::new(); instructions.len()];
+    let mut depth = 0usize;
+    let mut reads = Vec::new();
+    for (pc, instruction) in instructions.iter().enumerate() {
+        for next in instruction.successors(pc).into_iter().flatten() {
+            predecessors[next].push(pc);
+        }
+        if depth > 0 {
+            memoizable[pc] = false;
+        }
+        match instruction {
+            Instruction::MarkStack(_) => depth += 1,
+            Instruction::CutStack(_) => depth -= 1,
+            _ => {}
+        }
+        let mut slots = Vec::new();
+        instruction.read_slots(&mut slots);
+        reads.extend(slots.into_iter().map(|slot| (pc, slot)));
+    }
+
+    for (reader, slot) in reads {
+        let mut pending = vec![reader];
+        let mut seen = vec![false; instructions.len()];
+        while let Some(pc) = pending.pop() {
+            if std::mem::replace(&mut seen[pc], true) {
+                continue;
+            }
+            memoizable[pc] = false;
+            pending.extend(predecessors[pc].iter().copied().filter(|&previous| {
+                !matches!(instructions[previous], Instruction::SaveCaptureStart(set) if set == slot)
+            }));
+        }
+    }
+
+    let empty_successors = instructions
+        .iter()
+        .enumerate()
+        .map(|(pc, instruction)| {
+            if instruction.always_consumes() {
+                Vec::new()
+            } else {
+                instruction.successors(pc).into_iter().flatten().collect()
+            }
+        })
+        .collect::<Vec<_>>();
+    let mut empty_predecessors = vec![Vec::new(); instructions.len()];
+    for (pc, successors) in empty_successors.iter().enumerate() {
+        for &next in successors {
+            empty_predecessors[next].push(pc);
+        }
+    }
+    let traced = (0..instructions.len())
+        .filter(|&pc| !memoizable[pc])
+        .collect::<Vec<_>>();
+    for pc in traced {
+        let forward = reachable(pc, &empty_successors);
+        let backward = reachable(pc, &empty_predecessors);
+        for (other, memoizable) in memoizable.iter_mut().enumerate() {
+            if forward[other] && backward[other] {
+                *memoizable = false;
+            }
+        }
+    }
+    memoizable
+}
+
+/// The pcs reachable from `start` in one or more steps along `edges`.
+fn reachable(start: usize, edges: &[Vec<usize>]) -> Vec<bool> {
+    let mut seen = vec![false; edges.len()];
+    let mut pending = edges[start].clone();
+    while let Some(pc) = pending.pop() {
+        if !std::mem::replace(&mut seen[pc], true) {
+            pending.extend(edges[pc].iter().copied());
+        }
+    }
+    seen
 }
 
 fn normalize_patterns(patterns: Vec<Pattern>) -> Vec<Pattern> {
@@ -991,6 +2072,19 @@ fn normalize_patterns(patterns: Vec<Pattern>) -> Vec<Pattern> {
                 patterns: normalize_patterns(patterns),
                 count,
             },
+            Pattern::AtomicGroup { patterns, count } => Pattern::AtomicGroup {
+                patterns: normalize_patterns(patterns),
+                count,
+            },
+            Pattern::Lookaround {
+                ahead,
+                negated,
+                patterns,
+            } => Pattern::Lookaround {
+                ahead,
+                negated,
+                patterns: normalize_patterns(patterns),
+            },
             other => other,
         };
 
@@ -1007,6 +2101,10 @@ fn normalize_patterns(patterns: Vec<Pattern>) -> Vec<Pattern> {
 }
 
 fn merge_adjacent_simple(previous: &mut Pattern, next: &Pattern) -> bool {
+    if previous.count().is_lazy() || next.count().is_lazy() {
+        return false;
+    }
+
     match (previous, next) {
         (Pattern::Literal(left, left_count), Pattern::Literal(right, right_count))
             if left == right =>
@@ -1047,7 +2145,9 @@ fn collect_referenced_groups(patterns: &[Pattern], referenced: &mut Vec<usize>)
                     collect_referenced_groups(alternative, referenced);
                 }
             }
-            Pattern::CapturedGroup { patterns, .. } => {
+            Pattern::CapturedGroup { patterns, .. }
+            | Pattern::AtomicGroup { patterns, .. }
+            | Pattern::Lookaround { patterns, .. } => {
                 collect_referenced_groups(patterns, referenced);
             }
             Pattern::Backreference(group) => referenced.push(group - 1),
@@ -1055,11 +2155,48 @@ fn collect_referenced_groups(patterns: &[Pattern], referenced: &mut Vec<usize>)
             | Pattern::Digit(_)
             | Pattern::Alphanumeric(_)
             | Pattern::Wildcard(_)
-            | Pattern::CharGroup(_, _) => {}
+            | Pattern::CharGroup(_, _)
+            | Pattern::WordBoundary
+            | Pattern::NotWordBoundary
+            | Pattern::StartAnchor
+            | Pattern::EndAnchor => {}
         }
     }
 }
 
+fn capture_group_count(patterns: &[Pattern]) -> usize {
+    patterns
+        .iter()
+        .map(|pattern| match pattern {
+            Pattern::Alternation {
+                idx, alternatives, ..
+            } => alternatives
+                .iter()
+                .map(|alternative| capture_group_count(alternative))
+                .max()
+                .unwrap_or(0)
+                .max(idx.map_or(0, |idx| idx + 1)),
+            Pattern::CapturedGroup { idx, patterns, .. } => {
+                capture_group_count(patterns).max(idx.map_or(0, |idx| idx + 1))
+            }
+            Pattern::AtomicGroup { patterns, .. } | Pattern::Lookaround { patterns, .. } => {
+                capture_group_count(patterns)
+            }
+            Pattern::Literal(_, _)
+            | Pattern::Digit(_)
+            | Pattern::Alphanumeric(_)
+            | Pattern::Wildcard(_)
+            | Pattern::CharGroup(_, _)
+            | Pattern::Backreference(_)
+            | Pattern::WordBoundary
+            | Pattern::NotWordBoundary
+            | Pattern::StartAnchor
+            | Pattern::EndAnchor => 0,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
 fn build_group_slots(referenced_groups: &[usize]) -> Vec<Option<usize>> {
     let Some(max_group) = referenced_groups.iter().copied().max() else {
         return Vec::new();
@@ -1072,10 +2209,11 @@ fn build_group_slots(referenced_groups: &[usize]) -> Vec<Option<usize>> {
     slots
 }
 
-fn detect_fast_path(patterns: &[Pattern]) -> Option<FastPath> {
+fn detect_fast_path(patterns: &[Pattern], dot_matches_new_line: bool) -> Option<FastPath> {
     detect_single_capture_literal_backref(patterns)
         .map(FastPath::SingleCaptureLiteralBackref)
         .or_else(|| detect_two_part_replay_backref(patterns).map(FastPath::TwoPartReplayBackref))
+        .or_else(|| SegmentPlan::plan(patterns, dot_matches_new_line).map(FastPath::Segments))
 }
 
 fn detect_single_capture_literal_backref(
@@ -1132,7 +2270,7 @@ fn detect_group_replay_backref(patterns: &[Pattern]) -> Option<TwoPartReplayBack
         return None;
     };
 
-    if *idx != 0 || !count.is_exactly_one() {
+    if *idx != Some(0) || !count.is_exactly_one() {
         return None;
     }
 
@@ -1178,7 +2316,7 @@ fn captured_single_pattern(pattern: &Pattern, expected_idx: usize) -> Option<&Pa
         return None;
     };
 
-    if *idx != expected_idx || !count.is_exactly_one() {
+    if *idx != Some(expected_idx) || !count.is_exactly_one() {
         return None;
     }
 
@@ -1261,20 +2399,15 @@ fn matches_simple_variable_prefix(pattern: &Pattern, start_predicate: &StartPred
             .is_some_and(|candidate| candidate == start_predicate)
 }
 
+/// The predicate every match must start with, if the first consuming pattern
+/// is required. An optional first pattern means a match may start with
+/// whatever follows it, so no single predicate applies.
 fn first_required_start_predicate(patterns: &[Pattern]) -> Option<StartPredicate> {
-    for pattern in patterns {
-        if let Some(predicate) = required_start_predicate(pattern) {
-            if pattern.count().repetition_bounds().0 > 0 {
-                return Some(predicate);
-            }
-        }
-
-        if pattern.count().repetition_bounds().0 > 0 {
-            return None;
-        }
+    let pattern = patterns.iter().find(|pattern| !pattern.is_zero_width())?;
+    if pattern.count().repetition_bounds().0 == 0 {
+        return None;
     }
-
-    None
+    required_start_predicate(pattern)
 }
 
 fn required_start_predicate(pattern: &Pattern) -> Option<StartPredicate> {
@@ -1284,7 +2417,9 @@ fn required_start_predicate(pattern: &Pattern) -> Option<StartPredicate> {
         Pattern::Alphanumeric(_) => Some(StartPredicate::Word),
         Pattern::Wildcard(_) => Some(StartPredicate::Wildcard),
         Pattern::CharGroup(group, _) => Some(StartPredicate::CharGroup(group.clone())),
-        Pattern::CapturedGroup { patterns, .. } => first_required_start_predicate(patterns),
+        Pattern::CapturedGroup { patterns, .. } | Pattern::AtomicGroup { patterns, .. } => {
+            first_required_start_predicate(patterns)
+        }
         Pattern::Alternation { alternatives, .. } => {
             let mut predicates = alternatives
                 .iter()
@@ -1296,7 +2431,12 @@ fn required_start_predicate(pattern: &Pattern) -> Option<StartPredicate> {
                 .all(|candidate| candidate == first)
                 .then_some(first)
         }
-        Pattern::Backreference(_) => None,
+        Pattern::Backreference(_)
+        | Pattern::WordBoundary
+        | Pattern::NotWordBoundary
+        | Pattern::StartAnchor
+        | Pattern::EndAnchor
+        | Pattern::Lookaround { .. } => None,
     }
 }
 
@@ -1322,32 +2462,58 @@ fn literal_run_atom(pattern: &Pattern) -> Option<(char, usize)> {
     }
 }
 
-fn fixed_width(pattern: &Pattern) -> Option<usize> {
+/// What `fixed_width` measures: UTF-8 bytes for candidate offsets, or
+/// characters for stepping back over a lookbehind.
+#[derive(Clone, Copy)]
+enum WidthUnit {
+    Bytes,
+    Chars,
+}
+
+fn fixed_width(pattern: &Pattern, unit: WidthUnit) -> Option<usize> {
     let repetitions = pattern.count().fixed_repetitions()?;
-    let single = match pattern {
-        Pattern::Literal(_, _)
-        | Pattern::Digit(_)
-        | Pattern::Alphanumeric(_)
-        | Pattern::Wildcard(_)
-        | Pattern::CharGroup(_, _) => Some(1),
-        Pattern::CapturedGroup { patterns, .. } => sequence_fixed_width(patterns),
-        Pattern::Alternation { alternatives, .. } => {
+    let single = match (pattern, unit) {
+        (Pattern::Literal(ch, _), WidthUnit::Bytes) => Some(ch.len_utf8()),
+        (Pattern::CharGroup(group, _), WidthUnit::Bytes) => group.is_ascii_only().then_some(1),
+        (Pattern::Digit(_) | Pattern::Alphanumeric(_) | Pattern::Wildcard(_), WidthUnit::Bytes) => {
+            None
+        }
+        (
+            Pattern::Literal(..)
+            | Pattern::CharGroup(..)
+            | Pattern::Digit(_)
+            | Pattern::Alphanumeric(_)
+            | Pattern::Wildcard(_),
+            WidthUnit::Chars,
+        ) => Some(1),
+        (Pattern::CapturedGroup { patterns, .. } | Pattern::AtomicGroup { patterns, .. }, _) => {
+            sequence_fixed_width(patterns, unit)
+        }
+        (Pattern::Alternation { alternatives, .. }, _) => {
             let mut widths = alternatives
                 .iter()
-                .map(|alternative| sequence_fixed_width(alternative));
+                .map(|alternative| sequence_fixed_width(alternative, unit));
             let first = widths.next()??;
             widths.all(|width| width == Some(first)).then_some(first)
         }
-        Pattern::Backreference(_) => None,
+        (
+            Pattern::WordBoundary
+            | Pattern::NotWordBoundary
+            | Pattern::StartAnchor
+            | Pattern::EndAnchor
+            | Pattern::Lookaround { .. },
+            _,
+        ) => Some(0),
+        (Pattern::Backreference(_), _) => None,
     }?;
 
     Some(single * repetitions)
 }
 
-fn sequence_fixed_width(patterns: &[Pattern]) -> Option<usize> {
+fn sequence_fixed_width(patterns: &[Pattern], unit: WidthUnit) -> Option<usize> {
     let mut total = 0;
     for pattern in patterns {
-        total += fixed_width(pattern)?;
+        total += fixed_width(pattern, unit)?;
     }
     Some(total)
 }
@@ -1397,6 +2563,8 @@ fn visit_scan_candidates<T>(
     None
 }
 
+/// Visits every start inside the run of `predicate` characters that ends at
+/// `upper_bound`, leftmost first.
 fn visit_bounded_backward_candidates<T>(
     input: &str,
     scan_start: usize,
@@ -1404,10 +2572,6 @@ fn visit_bounded_backward_candidates<T>(
     predicate: &StartPredicate,
     mut visit: impl FnMut(usize) -> Option<T>,
 ) -> Option<T> {
-    if upper_bound < scan_start || !predicate.matches_at(input, upper_bound) {
-        return None;
-    }
-
     let mut earliest = upper_bound;
     while let Some(previous) = previous_char_boundary(input, earliest) {
         if previous < scan_start || !predicate.matches_at(input, previous) {
@@ -1417,13 +2581,10 @@ fn visit_bounded_backward_candidates<T>(
     }
 
     let mut current = earliest;
-    loop {
+    while current < upper_bound {
         if let Some(result) = visit(current) {
             return Some(result);
         }
-        if current == upper_bound {
-            break;
-        }
         current = next_char_boundary(input, current).expect("candidate boundary should advance");
     }
 
@@ -1442,20 +2603,31 @@ fn matches_literal(input: &str, pos: usize, expected: char) -> bool {
 }
 
 fn matches_digit(input: &str, pos: usize) -> bool {
-    match_char(input, pos, |current| current.is_ascii_digit()).is_some()
+    match_char(input, pos, is_digit_char).is_some()
 }
 
 fn matches_word(input: &str, pos: usize) -> bool {
-    match_char(input, pos, |current| {
-        current.is_ascii_alphanumeric() || current == '_'
-    })
-    .is_some()
+    match_char(input, pos, is_word_char).is_some()
 }
 
 fn matches_group(input: &str, pos: usize, group: &CharGroup) -> bool {
     match_char(input, pos, |current| group.matches(current)).is_some()
 }
 
+/// Whether `pos` sits between a word character and a non-word character,
+/// treating the ends of the input as non-word.
+fn is_word_boundary(input: &str, pos: usize) -> bool {
+    let before = input
+        .get(..pos)
+        .and_then(|text| text.chars().next_back())
+        .is_some_and(is_word_char);
+    let after = input
+        .get(pos..)
+        .and_then(|text| text.chars().next())
+        .is_some_and(is_word_char);
+    before != after
+}
+
 fn combine_widths(left: Option<usize>, right: Option<usize>) -> Option<usize> {
     Some(left? + right?)
 }
@@ -1478,10 +2650,16 @@ fn previous_char_boundary(input: &str, pos: usize) -> Option<usize> {
     input.get(..pos)?.char_indices().last().map(|(idx, _)| idx)
 }
 
-fn advance_after_match(input: &str, start: usize, end: usize) -> usize {
-    if end > start {
-        end
-    } else {
-        next_char_boundary(input, start).unwrap_or(input.len())
+/// The first line start at or after `pos`, counting the end of an input that
+/// ends in `\n` as the start of an empty line.
+fn line_start_at_or_after(input: &str, pos: usize) -> Option<usize> {
+    if pos == 0 {
+        return Some(0);
     }
+    input
+        .as_bytes()
+        .get(pos - 1..)?
+        .iter()
+        .position(|byte| *byte == b'\n')
+        .map(|offset| pos + offset)
 }
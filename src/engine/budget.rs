@@ -0,0 +1,97 @@
+use std::{
+    cell::Cell,
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// How often the VM looks at the clock, in steps.
+const CLOCK_CHECK_INTERVAL: u64 = 4096;
+
+/// Bounds on the work the backtracking VM may do. `None` leaves that bound
+/// off. Patterns that run on `regex_automata` never backtrack and ignore it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BacktrackLimit {
+    /// VM steps allowed for one line, or for the whole buffer in multiline
+    /// mode.
+    pub steps_per_line: Option<u64>,
+    /// VM steps allowed across every line of one file.
+    pub steps_per_file: Option<u64>,
+    /// Wall-clock time allowed for one file.
+    pub timeout: Option<Duration>,
+}
+
+/// The backtracking work left for one file. Create one per file with
+/// [`CompiledRegex::search_budget`](super::CompiledRegex::search_budget); once
+/// any bound is hit, every later search with it fails.
+#[derive(Debug)]
+pub struct SearchBudget {
+    limit: BacktrackLimit,
+    deadline: Option<Instant>,
+    line_steps: Cell<u64>,
+    file_steps: Cell<u64>,
+    exceeded: Cell<bool>,
+}
+
+/// A search ran out of its [`SearchBudget`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BacktrackLimitExceeded;
+
+impl SearchBudget {
+    #[must_use]
+    pub fn new(limit: BacktrackLimit) -> Self {
+        Self {
+            limit,
+            deadline: limit.timeout.map(|timeout| Instant::now() + timeout),
+            line_steps: Cell::new(0),
+            file_steps: Cell::new(0),
+            exceeded: Cell::new(false),
+        }
+    }
+
+    #[must_use]
+    pub fn unlimited() -> Self {
+        Self::new(BacktrackLimit::default())
+    }
+
+    pub(crate) fn start_line(&self) {
+        self.line_steps.set(0);
+    }
+
+    /// Spends one VM step, and returns whether the search may go on.
+    pub(crate) fn step(&self) -> bool {
+        if self.exceeded.get() {
+            return false;
+        }
+        let line_steps = self.line_steps.get() + 1;
+        let file_steps = self.file_steps.get() + 1;
+        self.line_steps.set(line_steps);
+        self.file_steps.set(file_steps);
+        let limit = &self.limit;
+        let over = limit.steps_per_line.is_some_and(|max| line_steps > max)
+            || limit.steps_per_file.is_some_and(|max| file_steps > max)
+            || (file_steps.is_multiple_of(CLOCK_CHECK_INTERVAL) && self.past_deadline());
+        self.exceeded.set(over);
+        !over
+    }
+
+    fn past_deadline(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    pub(crate) fn check(&self) -> Result<(), BacktrackLimitExceeded> {
+        if self.exceeded.get() {
+            Err(BacktrackLimitExceeded)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl fmt::Display for BacktrackLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("pattern too complex for this input")
+    }
+}
+
+impl std::error::Error for BacktrackLimitExceeded {}
@@ -0,0 +1,115 @@
+use std::fmt::Debug;
+
+use super::{automata::AutomataSearch, backref::BackreferenceSearch, RegexOptions, SearchBudget};
+
+/// Runs a backreference-free pattern on the backreference VM as well as on
+/// `regex_automata`, and panics when the two disagree.
+pub(crate) struct DifferentialCheck {
+    pattern: String,
+    reference: AutomataSearch,
+    vm: BackreferenceSearch,
+}
+
+impl DifferentialCheck {
+    /// Returns `None` when the VM cannot compile `pattern`, so there is
+    /// nothing to compare.
+    pub(crate) fn new(pattern: &str, options: RegexOptions) -> Option<Self> {
+        let vm = BackreferenceSearch::new(pattern, options).ok()?;
+        Some(Self {
+            pattern: pattern.to_string(),
+            reference: AutomataSearch::new(pattern, options).ok()?,
+            vm,
+        })
+    }
+
+    pub(crate) fn check_spans(&self, input: &str) {
+        self.compare(
+            input,
+            self.reference.find_all(input),
+            self.vm
+                .find_all(input, &SearchBudget::unlimited())
+                .expect("an unlimited budget never runs out"),
+        );
+    }
+
+    pub(crate) fn check_captures(&self, input: &str) {
+        self.compare(
+            input,
+            self.reference.find_all_captures(input),
+            self.vm
+                .find_all_captures(input, &SearchBudget::unlimited())
+                .expect("an unlimited budget never runs out"),
+        );
+    }
+
+    fn compare<T: PartialEq + Debug>(&self, input: &str, expected: Vec<T>, actual: Vec<T>) {
+        assert!(
+            expected == actual,
+            "differential check failed for pattern {:?} on input {input:?}\n  \
+             regex_automata: {expected:?}\n  backreference VM: {actual:?}",
+            self.pattern,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::RegexMatch;
+
+    #[test]
+    fn skips_patterns_the_vm_cannot_compile() {
+        let options = RegexOptions::default();
+        assert!(DifferentialCheck::new(r"\Aab", options).is_none());
+        assert!(DifferentialCheck::new("(?i)ab", options).is_none());
+        assert!(DifferentialCheck::new(r"(\w+)-\d", options).is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "differential check failed")]
+    fn reports_disagreements() {
+        let check = DifferentialCheck::new("a+", RegexOptions::default()).unwrap();
+        check.compare::<RegexMatch>(
+            "aa",
+            vec![RegexMatch { start: 0, end: 2 }],
+            vec![RegexMatch { start: 0, end: 1 }],
+        );
+    }
+
+    #[test]
+    fn captures_agree_for_repeated_groups() {
+        let check = DifferentialCheck::new(r"(\w)+-(\d)?", RegexOptions::default()).unwrap();
+        check.check_captures("ab- cd-1 é-");
+    }
+
+    #[test]
+    fn captures_agree_for_empty_iterations() {
+        for (pattern, input) in [
+            ("(a{0,3})*é", "é aé"),
+            ("(a{0,3})*?é", "é aé"),
+            ("(a|)+b", "b ab"),
+            ("(a?){2,}b", "b ab"),
+        ] {
+            let check = DifferentialCheck::new(pattern, RegexOptions::default()).unwrap();
+            check.check_spans(input);
+            check.check_captures(input);
+        }
+    }
+
+    #[test]
+    fn groups_under_zero_repetitions_are_dropped() {
+        for (pattern, input) in [
+            ("(a)(c){0}b", "ab"),
+            ("(a)(c){0}(b)", "ab"),
+            (r"b{0}?a1|(ab(?P<n>([ab]??\wé)){0}?ba| )", "a1"),
+        ] {
+            let check = DifferentialCheck::new(pattern, RegexOptions::default()).unwrap();
+            assert_eq!(
+                check.vm.captures_len(),
+                check.reference.captures_len(),
+                "pattern {pattern}"
+            );
+            check.check_captures(input);
+        }
+    }
+}
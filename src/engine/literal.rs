@@ -1,12 +1,13 @@
 use aho_corasick::{AhoCorasick, AhoCorasickBuilder, MatchKind};
 
-use super::{classify::LiteralSpec, LineCandidate, RegexMatch};
+use super::{classify::LiteralSpec, LineCandidate, RegexCaptures, RegexMatch};
 
 pub(crate) struct LiteralSearch {
     literals: Vec<String>,
     automaton: AhoCorasick,
     start_anchor: bool,
     end_anchor: bool,
+    grouped: bool,
 }
 
 impl LiteralSearch {
@@ -20,6 +21,7 @@ impl LiteralSearch {
             automaton,
             start_anchor: spec.start_anchor,
             end_anchor: spec.end_anchor,
+            grouped: spec.grouped,
         }
     }
 
@@ -67,6 +69,21 @@ impl LiteralSearch {
             .collect()
     }
 
+    pub(crate) fn find_all_captures(&self, input: &str) -> Vec<RegexCaptures> {
+        self.find_all(input)
+            .into_iter()
+            .map(|matched| RegexCaptures::new(vec![Some(matched); self.captures_len()]))
+            .collect()
+    }
+
+    pub(crate) fn captures_len(&self) -> usize {
+        if self.grouped {
+            2
+        } else {
+            1
+        }
+    }
+
     pub(crate) fn find_candidate_line(&self, input: &str, at: usize) -> Option<LineCandidate> {
         let offset = self.automaton.find(input.get(at..)?)?.start() + at;
         if self.start_anchor || self.end_anchor {
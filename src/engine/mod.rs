@@ -1,11 +1,16 @@
 mod automata;
 mod backref;
+mod budget;
 mod classify;
+mod differential;
 mod literal;
+mod syntax;
 
 use automata::AutomataSearch;
 use backref::BackreferenceSearch;
+pub use budget::{BacktrackLimit, BacktrackLimitExceeded, SearchBudget};
 use classify::{classify_regex, SearchStrategy};
+use differential::DifferentialCheck;
 use literal::LiteralSearch;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -14,6 +19,13 @@ pub struct RegexMatch {
     pub end: usize,
 }
 
+/// Spans of every capture group for one match. Group 0 is the whole match and
+/// groups that did not participate are `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegexCaptures {
+    groups: Vec<Option<RegexMatch>>,
+}
+
 #[doc(hidden)]
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LineCandidate {
@@ -21,8 +33,25 @@ pub enum LineCandidate {
     Candidate(usize),
 }
 
+/// Options that change how a pattern is compiled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RegexOptions {
+    /// Match across the whole buffer: `^` and `$` match at line boundaries and
+    /// classes may match `\n`.
+    pub multiline: bool,
+    /// Let `.` match `\n` as well. Only meaningful together with `multiline`.
+    pub dot_matches_new_line: bool,
+    /// Also run patterns without backreferences on the backreference engine
+    /// and panic if it disagrees with `regex_automata`. For testing only.
+    pub differential: bool,
+    /// Bounds on the work the backtracking VM may do per line and per file.
+    pub backtrack_limit: BacktrackLimit,
+}
+
 pub struct CompiledRegex {
     plan: SearchPlan,
+    differential: Option<Box<DifferentialCheck>>,
+    backtrack_limit: BacktrackLimit,
 }
 
 enum SearchPlan {
@@ -32,15 +61,36 @@ enum SearchPlan {
 }
 
 impl CompiledRegex {
-    fn new(regex: &str) -> Self {
+    fn new(regex: &str, options: RegexOptions) -> anyhow::Result<Self> {
         let plan = match classify_regex(regex) {
-            SearchStrategy::Literal(spec) => SearchPlan::Literal(LiteralSearch::new(spec)),
-            SearchStrategy::Automata => SearchPlan::Automata(AutomataSearch::new(regex)),
+            SearchStrategy::Literal(spec)
+                if !(options.multiline && (spec.start_anchor || spec.end_anchor)) =>
+            {
+                SearchPlan::Literal(LiteralSearch::new(spec))
+            }
+            SearchStrategy::Literal(_) | SearchStrategy::Automata => {
+                SearchPlan::Automata(AutomataSearch::new(regex, options)?)
+            }
             SearchStrategy::Backreference => {
-                SearchPlan::Backreference(Box::new(BackreferenceSearch::new(regex)))
+                SearchPlan::Backreference(Box::new(BackreferenceSearch::new(regex, options)?))
             }
+            SearchStrategy::Invalid(err) => return Err(err),
         };
-        Self { plan }
+        let differential = (options.differential && !matches!(plan, SearchPlan::Backreference(_)))
+            .then(|| DifferentialCheck::new(regex, options).map(Box::new))
+            .flatten();
+        Ok(Self {
+            plan,
+            differential,
+            backtrack_limit: options.backtrack_limit,
+        })
+    }
+
+    /// A fresh budget for searching one file, with this pattern's
+    /// backtrack limit.
+    #[must_use]
+    pub fn search_budget(&self) -> SearchBudget {
+        SearchBudget::new(self.backtrack_limit)
     }
 
     #[doc(hidden)]
@@ -53,6 +103,25 @@ impl CompiledRegex {
         }
     }
 
+    /// Number of capture groups, including the implicit whole-match group 0.
+    #[must_use]
+    pub fn captures_len(&self) -> usize {
+        match &self.plan {
+            SearchPlan::Literal(search) => search.captures_len(),
+            SearchPlan::Automata(search) => search.captures_len(),
+            SearchPlan::Backreference(search) => search.captures_len(),
+        }
+    }
+
+    #[must_use]
+    pub fn capture_index(&self, name: &str) -> Option<usize> {
+        match &self.plan {
+            SearchPlan::Literal(_) => None,
+            SearchPlan::Automata(search) => search.capture_index(name),
+            SearchPlan::Backreference(search) => search.capture_index(name),
+        }
+    }
+
     #[doc(hidden)]
     #[must_use]
     pub fn supports_candidate_lines(&self) -> bool {
@@ -64,16 +133,118 @@ impl CompiledRegex {
     }
 }
 
+impl RegexCaptures {
+    pub(crate) fn new(groups: Vec<Option<RegexMatch>>) -> Self {
+        Self { groups }
+    }
+
+    #[must_use]
+    pub fn get(&self, idx: usize) -> Option<RegexMatch> {
+        self.groups.get(idx).copied().flatten()
+    }
+
+    #[must_use]
+    pub fn whole(&self) -> RegexMatch {
+        self.get(0).expect("group 0 always participates in a match")
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.groups.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+}
+
+/// Like [`compile_regex_with_options`], with the default options.
+///
+/// # Panics
+///
+/// Panics when `regex` is invalid or unsupported.
 #[must_use]
 pub fn compile_regex(regex: &str) -> CompiledRegex {
-    CompiledRegex::new(regex)
+    compile_regex_with_options(regex, RegexOptions::default())
 }
 
+/// Like [`try_compile_regex_with_options`], for patterns known to be valid.
+///
+/// # Panics
+///
+/// Panics when `regex` is invalid or unsupported.
+#[must_use]
+pub fn compile_regex_with_options(regex: &str, options: RegexOptions) -> CompiledRegex {
+    try_compile_regex_with_options(regex, options)
+        .unwrap_or_else(|err| panic!("invalid regex: {err:#}"))
+}
+
+/// Compiles `regex`, or reports why it is invalid or unsupported, such as a
+/// backreference to a missing group.
+pub fn try_compile_regex_with_options(
+    regex: &str,
+    options: RegexOptions,
+) -> anyhow::Result<CompiledRegex> {
+    CompiledRegex::new(regex, options)
+}
+
+/// Like [`find_all_regex_spans_budgeted`], with a fresh budget.
+///
+/// # Panics
+///
+/// Panics when the search goes over the pattern's backtrack limit.
 #[must_use]
 pub fn find_all_regex_spans_compiled(input_line: &str, regex: &CompiledRegex) -> Vec<RegexMatch> {
-    match &regex.plan {
+    find_all_regex_spans_budgeted(input_line, regex, &regex.search_budget())
+        .unwrap_or_else(|err| panic!("{err}"))
+}
+
+/// Like [`find_all_regex_captures_budgeted`], with a fresh budget.
+///
+/// # Panics
+///
+/// Panics when the search goes over the pattern's backtrack limit.
+#[must_use]
+pub fn find_all_regex_captures_compiled(
+    input_line: &str,
+    regex: &CompiledRegex,
+) -> Vec<RegexCaptures> {
+    find_all_regex_captures_budgeted(input_line, regex, &regex.search_budget())
+        .unwrap_or_else(|err| panic!("{err}"))
+}
+
+/// Every match in `input_line`, spending backtracking work from `budget`.
+pub fn find_all_regex_spans_budgeted(
+    input_line: &str,
+    regex: &CompiledRegex,
+    budget: &SearchBudget,
+) -> Result<Vec<RegexMatch>, BacktrackLimitExceeded> {
+    if let Some(check) = &regex.differential {
+        check.check_spans(input_line);
+    }
+    budget.start_line();
+    Ok(match &regex.plan {
         SearchPlan::Literal(search) => search.find_all(input_line),
         SearchPlan::Automata(search) => search.find_all(input_line),
-        SearchPlan::Backreference(search) => search.find_all(input_line),
+        SearchPlan::Backreference(search) => search.find_all(input_line, budget)?,
+    })
+}
+
+/// Every match in `input_line` with its capture groups, spending
+/// backtracking work from `budget`.
+pub fn find_all_regex_captures_budgeted(
+    input_line: &str,
+    regex: &CompiledRegex,
+    budget: &SearchBudget,
+) -> Result<Vec<RegexCaptures>, BacktrackLimitExceeded> {
+    if let Some(check) = &regex.differential {
+        check.check_captures(input_line);
     }
+    budget.start_line();
+    Ok(match &regex.plan {
+        SearchPlan::Literal(search) => search.find_all_captures(input_line),
+        SearchPlan::Automata(search) => search.find_all_captures(input_line),
+        SearchPlan::Backreference(search) => search.find_all_captures(input_line, budget)?,
+    })
 }
@@ -0,0 +1,214 @@
+use std::cmp::Reverse;
+
+use super::{Ast, AstKind, Bracket, ClassItem, GroupKind};
+
+/// The most literals a set may hold before it stops being worth tracking.
+const MAX_LITERALS: usize = 32;
+
+/// Bracket expressions with at most this many members expand into literals,
+/// so that `[Ee]rror` requires `Error` or `error`.
+const MAX_BRACKET_EXPANSION: u32 = 4;
+
+/// What a node says about the literals of its matches.
+struct Literals {
+    /// Every string the node matches, when they are few and all literal.
+    exact: Option<Vec<String>>,
+    /// Non-empty strings one of which every match contains.
+    required: Option<Vec<String>>,
+}
+
+impl Ast {
+    /// Literals one of which every match must contain, anywhere in it, used
+    /// to find candidate lines.
+    pub(crate) fn required_literals(&self) -> Option<Vec<String>> {
+        let literals = self.literals().required?;
+        // A line that holds `foobar` also holds `bar`.
+        Some(
+            literals
+                .iter()
+                .filter(|literal| {
+                    !literals.iter().any(|other| {
+                        other.len() < literal.len() && literal.contains(other.as_str())
+                    })
+                })
+                .cloned()
+                .collect(),
+        )
+    }
+
+    fn literals(&self) -> Literals {
+        let mut literals = match &self.kind {
+            AstKind::Empty | AstKind::Assertion(_) => Literals::exact(vec![String::new()]),
+            AstKind::Literal(ch) => Literals::exact(vec![ch.to_string()]),
+            AstKind::Bracket(bracket) => bracket.members().map_or(Literals::unknown(), |members| {
+                Literals::exact(members.into_iter().map(String::from).collect())
+            }),
+            AstKind::Dot | AstKind::Class(_) | AstKind::Backreference(_) | AstKind::Flags(_) => {
+                Literals::unknown()
+            }
+            AstKind::Group(group) => match group.kind {
+                GroupKind::Capture { .. } | GroupKind::NonCapturing | GroupKind::Atomic => {
+                    group.ast.literals()
+                }
+                GroupKind::Lookaround { .. } => Literals::exact(vec![String::new()]),
+                // Flags such as `(?i:...)` change what the literals match.
+                GroupKind::Flags(_) => Literals::unknown(),
+            },
+            AstKind::Concat(items) => Self::concat_literals(items),
+            AstKind::Alternation(branches) => {
+                let branches = branches.iter().map(Ast::literals).collect::<Vec<_>>();
+                Literals {
+                    exact: union(branches.iter().map(|branch| branch.exact.clone())),
+                    required: union(branches.iter().map(|branch| branch.required.clone())),
+                }
+            }
+            AstKind::Repetition(repetition) => {
+                let inner = repetition.ast.literals();
+                let exact = match (&inner.exact, repetition.max) {
+                    (Some(exact), Some(max)) if max <= MAX_LITERALS => {
+                        union((repetition.min..=max).map(|count| power(exact, count)))
+                    }
+                    _ => None,
+                };
+                Literals {
+                    exact,
+                    required: inner.required.filter(|_| repetition.min > 0),
+                }
+            }
+        };
+        if let Some(exact) = literals.exact.clone() {
+            literals.consider(exact);
+        }
+        literals
+    }
+
+    /// Joins runs of exactly-known items into longer literals, and keeps the
+    /// best required set found along the way.
+    fn concat_literals(items: &[Ast]) -> Literals {
+        let mut literals = Literals::unknown();
+        let mut whole = Some(vec![String::new()]);
+        let mut run = vec![String::new()];
+        for item in items {
+            // A flag item changes how the rest of its group matches.
+            if matches!(item.kind, AstKind::Flags(_)) {
+                whole = None;
+                break;
+            }
+            let item = item.literals();
+            if let Some(required) = item.required {
+                literals.consider(required);
+            }
+            whole = whole
+                .zip(item.exact.as_ref())
+                .and_then(|(whole, exact)| product(&whole, exact));
+            match item.exact {
+                Some(exact) => {
+                    run = product(&run, &exact).unwrap_or_else(|| {
+                        literals.consider(std::mem::take(&mut run));
+                        exact
+                    });
+                }
+                None => literals.consider(std::mem::replace(&mut run, vec![String::new()])),
+            }
+        }
+        literals.consider(run);
+        literals.exact = whole;
+        literals
+    }
+}
+
+impl Literals {
+    fn exact(strings: Vec<String>) -> Self {
+        Self {
+            exact: Some(strings),
+            required: None,
+        }
+    }
+
+    fn unknown() -> Self {
+        Self {
+            exact: None,
+            required: None,
+        }
+    }
+
+    /// Keeps `candidate` as the required set if it is valid and beats the
+    /// current one: its shortest literal is longer, or as long with fewer
+    /// literals to search for.
+    fn consider(&mut self, candidate: Vec<String>) {
+        let Some(candidate_score) = score(&candidate) else {
+            return;
+        };
+        if self
+            .required
+            .as_deref()
+            .and_then(score)
+            .is_none_or(|current| candidate_score > current)
+        {
+            self.required = Some(candidate);
+        }
+    }
+}
+
+impl Bracket {
+    /// The characters a small, non-negated bracket expression matches.
+    fn members(&self) -> Option<Vec<char>> {
+        if self.negated {
+            return None;
+        }
+        let mut members = Vec::new();
+        let mut size = 0;
+        for item in &self.items {
+            let ClassItem::Range(start, end) = *item else {
+                return None;
+            };
+            size += (end as u32).checked_sub(start as u32)? + 1;
+            if size > MAX_BRACKET_EXPANSION {
+                return None;
+            }
+            members.extend(start..=end);
+        }
+        members.sort_unstable();
+        members.dedup();
+        (!members.is_empty()).then_some(members)
+    }
+}
+
+fn score(literals: &[String]) -> Option<(usize, Reverse<usize>)> {
+    let shortest = literals.iter().map(String::len).min()?;
+    (shortest > 0 && literals.len() <= MAX_LITERALS).then_some((shortest, Reverse(literals.len())))
+}
+
+/// Every concatenation of a string from `left` with one from `right`, or
+/// `None` when there would be too many.
+fn product(left: &[String], right: &[String]) -> Option<Vec<String>> {
+    if left.len() * right.len() > MAX_LITERALS {
+        return None;
+    }
+    let mut strings = left
+        .iter()
+        .flat_map(|prefix| right.iter().map(move |suffix| format!("{prefix}{suffix}")))
+        .collect::<Vec<_>>();
+    strings.sort_unstable();
+    strings.dedup();
+    Some(strings)
+}
+
+/// `strings` repeated `count` times.
+fn power(strings: &[String], count: usize) -> Option<Vec<String>> {
+    (0..count).try_fold(vec![String::new()], |acc, _| product(&acc, strings))
+}
+
+/// The union of every set, or `None` if any is unknown or it grows too big.
+fn union(sets: impl Iterator<Item = Option<Vec<String>>>) -> Option<Vec<String>> {
+    let mut strings = Vec::new();
+    for set in sets {
+        strings.extend(set?);
+        if strings.len() > MAX_LITERALS * 2 {
+            return None;
+        }
+    }
+    strings.sort_unstable();
+    strings.dedup();
+    (strings.len() <= MAX_LITERALS).then_some(strings)
+}
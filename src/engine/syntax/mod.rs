@@ -0,0 +1,165 @@
+mod literals;
+mod parser;
+
+use std::fmt;
+
+pub(crate) use parser::parse;
+
+/// A byte range in the pattern, for error messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Span {
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Ast {
+    pub(crate) kind: AstKind,
+    pub(crate) span: Span,
+}
+
+#[derive(Debug)]
+pub(crate) enum AstKind {
+    Empty,
+    Literal(char),
+    Dot,
+    /// A class escape such as `\d` or `\p{Greek}`.
+    Class(ClassItem),
+    Bracket(Bracket),
+    Assertion(Assertion),
+    /// A resolved one-based group number.
+    Backreference(usize),
+    /// A flag group such as `(?i)`, which applies to the rest of its group.
+    Flags(String),
+    Repetition(Repetition),
+    Group(Group),
+    Concat(Vec<Ast>),
+    Alternation(Vec<Ast>),
+}
+
+#[derive(Debug)]
+pub(crate) struct Repetition {
+    pub(crate) ast: Box<Ast>,
+    pub(crate) min: usize,
+    pub(crate) max: Option<usize>,
+    pub(crate) greed: Greed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Greed {
+    Greedy,
+    /// `*?`: prefers the fewest repetitions.
+    Lazy,
+    /// `*+`: never gives back a repetition once matched.
+    Possessive,
+}
+
+#[derive(Debug)]
+pub(crate) struct Group {
+    pub(crate) kind: GroupKind,
+    pub(crate) ast: Box<Ast>,
+}
+
+#[derive(Debug)]
+pub(crate) enum GroupKind {
+    /// A capturing group with its zero-based index.
+    Capture {
+        index: usize,
+        name: Option<String>,
+    },
+    NonCapturing,
+    /// `(?flags:...)`
+    Flags(String),
+    Atomic,
+    Lookaround {
+        ahead: bool,
+        negated: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Assertion {
+    Start,
+    End,
+    WordBoundary,
+    NotWordBoundary,
+}
+
+#[derive(Debug)]
+pub(crate) struct Bracket {
+    pub(crate) negated: bool,
+    pub(crate) items: Vec<ClassItem>,
+}
+
+/// One member of a bracket expression, or a standalone class escape. The
+/// `bool` marks a negated class such as `\D` or `[:^alpha:]`.
+#[derive(Debug)]
+pub(crate) enum ClassItem {
+    Range(char, char),
+    Perl(PerlClass, bool),
+    Unicode(String, bool),
+    Posix(String, bool),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PerlClass {
+    Digit,
+    Word,
+    Space,
+}
+
+impl Ast {
+    /// The items of a concatenation, or the node itself.
+    pub(crate) fn items(&self) -> &[Ast] {
+        match &self.kind {
+            AstKind::Concat(items) => items,
+            _ => std::slice::from_ref(self),
+        }
+    }
+
+    /// The alternatives of an alternation, or the node itself.
+    pub(crate) fn branches(&self) -> &[Ast] {
+        match &self.kind {
+            AstKind::Alternation(branches) => branches,
+            _ => std::slice::from_ref(self),
+        }
+    }
+
+    /// Whether this node or any node below it satisfies `pred`.
+    pub(crate) fn any(&self, pred: &impl Fn(&Ast) -> bool) -> bool {
+        pred(self)
+            || match &self.kind {
+                AstKind::Repetition(repetition) => repetition.ast.any(pred),
+                AstKind::Group(group) => group.ast.any(pred),
+                AstKind::Concat(asts) | AstKind::Alternation(asts) => {
+                    asts.iter().any(|ast| ast.any(pred))
+                }
+                AstKind::Empty
+                | AstKind::Literal(_)
+                | AstKind::Dot
+                | AstKind::Class(_)
+                | AstKind::Bracket(_)
+                | AstKind::Assertion(_)
+                | AstKind::Backreference(_)
+                | AstKind::Flags(_) => false,
+            }
+    }
+
+    /// The text matched by a sequence of plain literal characters.
+    pub(crate) fn literal_text(items: &[Ast]) -> Option<String> {
+        let literal = items
+            .iter()
+            .map(|item| match item.kind {
+                AstKind::Literal(ch) => Some(ch),
+                _ => None,
+            })
+            .collect::<Option<String>>()?;
+        (!literal.is_empty()).then_some(literal)
+    }
+}
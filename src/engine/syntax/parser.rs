@@ -0,0 +1,513 @@
+use std::{iter::Peekable, str::CharIndices};
+
+use anyhow::{anyhow, Error, Result};
+
+use super::{
+    Assertion, Ast, AstKind, Bracket, ClassItem, Greed, Group, GroupKind, PerlClass, Repetition,
+    Span,
+};
+
+/// Parses `pattern` into an AST. Errors name the offending byte range.
+pub(crate) fn parse(pattern: &str) -> Result<Ast> {
+    let mut parser = Parser {
+        pattern,
+        chars: pattern.char_indices().peekable(),
+        group_count: 0,
+        group_names: Vec::new(),
+    };
+    let ast = parser.parse_alternation()?;
+    if let Some((offset, _)) = parser.chars.next() {
+        return Err(parser.error(offset, "unopened group"));
+    }
+    Ok(ast)
+}
+
+struct Parser<'a> {
+    pattern: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    group_count: usize,
+    /// Named groups seen so far, with their zero-based capture indices.
+    group_names: Vec<(String, usize)>,
+}
+
+impl Parser<'_> {
+    fn parse_alternation(&mut self) -> Result<Ast> {
+        let start = self.offset();
+        let mut branches = vec![self.parse_concat()?];
+        while self.eat('|') {
+            branches.push(self.parse_concat()?);
+        }
+        if branches.len() == 1 {
+            return Ok(branches.pop().unwrap());
+        }
+        Ok(self.node(start, AstKind::Alternation(branches)))
+    }
+
+    fn parse_concat(&mut self) -> Result<Ast> {
+        let start = self.offset();
+        let mut items = Vec::new();
+        while let Some(&(_, c)) = self.chars.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            items.push(self.parse_repetition(atom)?);
+        }
+        Ok(match items.len() {
+            0 => self.node(start, AstKind::Empty),
+            1 => items.pop().unwrap(),
+            _ => self.node(start, AstKind::Concat(items)),
+        })
+    }
+
+    fn parse_atom(&mut self) -> Result<Ast> {
+        let offset = self.offset();
+        let (start, c) = self.next_or(offset, "unexpected end of pattern")?;
+        let kind = match c {
+            '\\' => self.parse_escape(start)?,
+            '[' => AstKind::Bracket(self.parse_bracket(start)?),
+            '(' => return self.parse_group(start),
+            '.' => AstKind::Dot,
+            '^' => AstKind::Assertion(Assertion::Start),
+            '$' => AstKind::Assertion(Assertion::End),
+            '?' | '*' | '+' | '{' => {
+                return Err(self.error(
+                    start,
+                    format!("repetition operator '{c}' has nothing to repeat"),
+                ));
+            }
+            literal => AstKind::Literal(literal),
+        };
+        Ok(self.node(start, kind))
+    }
+
+    /// Parses the quantifier after `atom`, if any. A trailing `?` makes it
+    /// lazy and a trailing `+` possessive.
+    fn parse_repetition(&mut self, atom: Ast) -> Result<Ast> {
+        let start = atom.span.start;
+        let (min, max) = match self.chars.peek() {
+            Some((_, '{')) => self.parse_counted()?,
+            Some((_, '?')) => self.consume((0, Some(1))),
+            Some((_, '*')) => self.consume((0, None)),
+            Some((_, '+')) => self.consume((1, None)),
+            _ => return Ok(atom),
+        };
+        if matches!(atom.kind, AstKind::Flags(_)) {
+            return Err(self.error(start, "repetition operator has nothing to repeat"));
+        }
+
+        let greed = if self.eat('?') {
+            Greed::Lazy
+        } else if self.eat('+') {
+            Greed::Possessive
+        } else {
+            Greed::Greedy
+        };
+        Ok(self.node(
+            start,
+            AstKind::Repetition(Repetition {
+                ast: Box::new(atom),
+                min,
+                max,
+                greed,
+            }),
+        ))
+    }
+
+    /// Parses `{n}`, `{n,}` or `{n,m}`.
+    fn parse_counted(&mut self) -> Result<(usize, Option<usize>)> {
+        let (start, _) = self.chars.next().expect("peeked '{'");
+        let min = self.parse_decimal(start, "Expected '{n}', '{n,}' or '{n,m}' quantifier")?;
+        if self.eat('}') {
+            return Ok((min, Some(min)));
+        }
+        if !self.eat(',') {
+            return Err(self.error(start, "Expected '{n}', '{n,}' or '{n,m}' quantifier"));
+        }
+        if self.eat('}') {
+            return Ok((min, None));
+        }
+        let max = self.parse_decimal(start, "Expected '{n,m}' quantifier")?;
+        if !self.eat('}') {
+            return Err(self.error(start, "Expected '{n,m}' quantifier"));
+        }
+        if min > max {
+            return Err(self.error(start, "Expected '{n,m}' quantifier with n <= m"));
+        }
+        Ok((min, Some(max)))
+    }
+
+    fn parse_decimal(&mut self, start: usize, message: &str) -> Result<usize> {
+        let mut digits = String::new();
+        while let Some((_, digit)) = self.chars.next_if(|(_, c)| c.is_ascii_digit()) {
+            digits.push(digit);
+        }
+        digits.parse().map_err(|_| self.error(start, message))
+    }
+
+    fn parse_escape(&mut self, start: usize) -> Result<AstKind> {
+        let (_, c) = self.next_or(start, "incomplete escape sequence")?;
+        Ok(match c {
+            'd' | 'D' => AstKind::Class(ClassItem::Perl(PerlClass::Digit, c == 'D')),
+            'w' | 'W' => AstKind::Class(ClassItem::Perl(PerlClass::Word, c == 'W')),
+            's' | 'S' => AstKind::Class(ClassItem::Perl(PerlClass::Space, c == 'S')),
+            'p' | 'P' => AstKind::Class(ClassItem::Unicode(
+                self.parse_unicode_class(start)?,
+                c == 'P',
+            )),
+            'b' => AstKind::Assertion(Assertion::WordBoundary),
+            'B' => AstKind::Assertion(Assertion::NotWordBoundary),
+            // Word-start and word-end assertions; the backtracking engine has
+            // no equivalent, so these patterns are left to regex_automata.
+            '<' | '>' => {
+                return Err(self.error(start, format!("unsupported word boundary assertion: \\{c}")))
+            }
+            c if c.is_ascii_digit() => {
+                let mut digits = String::from(c);
+                while let Some((_, digit)) = self.chars.next_if(|(_, c)| c.is_ascii_digit()) {
+                    digits.push(digit);
+                }
+                AstKind::Backreference(self.parse_group_number(start, &digits)?)
+            }
+            'k' => {
+                if !self.eat('<') {
+                    return Err(self.error(start, "Expected '<' after '\\k'"));
+                }
+                let name = self.parse_group_name(start)?;
+                AstKind::Backreference(self.resolve_group_name(start, &name)?)
+            }
+            'g' => AstKind::Backreference(self.parse_g_reference(start)?),
+            c => AstKind::Literal(self.parse_char_escape(start, c)?),
+        })
+    }
+
+    /// Parses the `{N}`, `{-N}` or `{name}` after `\g`. A relative `{-N}`
+    /// counts back from the most recently opened group.
+    fn parse_g_reference(&mut self, start: usize) -> Result<usize> {
+        if !self.eat('{') {
+            return Err(self.error(start, "Expected '{' after '\\g'"));
+        }
+        let reference = self.take_until('}', start, "Expected '}' after '\\g{'")?;
+
+        if let Some(relative) = reference.strip_prefix('-') {
+            let back = self.parse_group_number(start, relative)?;
+            return self
+                .group_count
+                .checked_sub(back)
+                .map(|idx| idx + 1)
+                .ok_or_else(|| {
+                    self.error(
+                        start,
+                        format!(
+                            "relative backreference \\g{{{reference}}} refers to a missing \
+                             capture group"
+                        ),
+                    )
+                });
+        }
+        if reference.starts_with(|c: char| c.is_ascii_digit()) {
+            return self.parse_group_number(start, &reference);
+        }
+        self.resolve_group_name(start, &reference)
+    }
+
+    /// Parses a one-based group number. Every digit belongs to the number, so
+    /// `\12` always means group 12; `\g{1}2` is group 1 followed by `2`.
+    fn parse_group_number(&self, start: usize, digits: &str) -> Result<usize> {
+        match digits.parse() {
+            Ok(0) => Err(self.error(start, "invalid backreference \\0")),
+            Ok(group) => Ok(group),
+            Err(_) => Err(self.error(start, format!("invalid backreference: {digits}"))),
+        }
+    }
+
+    /// The one-based capture index of a named group opened earlier.
+    fn resolve_group_name(&self, start: usize, name: &str) -> Result<usize> {
+        self.group_names
+            .iter()
+            .find(|(group_name, _)| group_name == name)
+            .map(|(_, idx)| idx + 1)
+            .ok_or_else(|| self.error(start, format!("unknown capture group name: {name}")))
+    }
+
+    /// Resolves an escape that stands for a single character: a control
+    /// character such as `\t`, a hex code point such as `\x41` or `\u{1F600}`,
+    /// or escaped punctuation.
+    fn parse_char_escape(&mut self, start: usize, c: char) -> Result<char> {
+        Ok(match c {
+            'a' => '\x07',
+            'f' => '\x0c',
+            't' => '\t',
+            'n' => '\n',
+            'r' => '\r',
+            'v' => '\x0b',
+            'x' => self.parse_hex_escape(start, 2)?,
+            'u' => self.parse_hex_escape(start, 4)?,
+            'U' => self.parse_hex_escape(start, 8)?,
+            c if !c.is_ascii_alphanumeric() => c,
+            unknown => {
+                return Err(self.error(start, format!("unsupported escape sequence: \\{unknown}")))
+            }
+        })
+    }
+
+    /// Parses the digits after `\x`, `\u` or `\U`: exactly `digits` hex
+    /// digits, or any number of them in braces.
+    fn parse_hex_escape(&mut self, start: usize, digits: usize) -> Result<char> {
+        let hex = if self.eat('{') {
+            self.take_until('}', start, "Expected '}' after hex escape")?
+        } else {
+            let hex = (0..digits)
+                .map_while(|_| self.chars.next().map(|(_, c)| c))
+                .collect::<String>();
+            if hex.chars().count() != digits {
+                return Err(self.error(start, format!("Expected {digits} hex digits in escape")));
+            }
+            hex
+        };
+        u32::from_str_radix(&hex, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| self.error(start, format!("invalid hex escape: {hex}")))
+    }
+
+    /// Parses the name after `\p`: either a single letter or `{Name}`.
+    fn parse_unicode_class(&mut self, start: usize) -> Result<String> {
+        match self.chars.next() {
+            Some((_, '{')) => self.take_until('}', start, "Expected '}' after Unicode class name"),
+            Some((_, c)) => Ok(c.to_string()),
+            None => Err(self.error(start, "Expected Unicode class name after '\\p'")),
+        }
+    }
+
+    /// Parses a bracket expression after its opening `[`.
+    fn parse_bracket(&mut self, start: usize) -> Result<Bracket> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let (offset, c) = self.next_or(start, "Expected ']' after group")?;
+            let member = match c {
+                // A leading ']' is a member rather than the end of the group.
+                ']' if !first => break,
+                '[' if self.eat(':') => {
+                    items.push(self.parse_posix_class(offset)?);
+                    None
+                }
+                '[' => return Err(self.error(offset, "nested character classes are not supported")),
+                '&' | '~' if self.eat(c) => {
+                    return Err(
+                        self.error(offset, "character class set operations are not supported")
+                    );
+                }
+                '\\' => self.parse_bracket_escape(offset, &mut items)?,
+                c => Some(c),
+            };
+            first = false;
+
+            let Some(member) = member else {
+                continue;
+            };
+            let mut lookahead = self.chars.clone();
+            let is_range = matches!(lookahead.next(), Some((_, '-')))
+                && lookahead.peek().is_some_and(|(_, end)| *end != ']');
+            if !is_range {
+                items.push(ClassItem::Range(member, member));
+                continue;
+            }
+            self.chars.next();
+            let (end_offset, end) = self.next_or(start, "Expected ']' after group")?;
+            let end = match end {
+                '\\' => self
+                    .parse_bracket_escape(end_offset, &mut items)?
+                    .ok_or_else(|| {
+                        self.error(
+                            end_offset,
+                            "Expected a single character at the end of a range",
+                        )
+                    })?,
+                end => end,
+            };
+            if member > end {
+                return Err(self.error(offset, format!("Invalid range {member}-{end} in group")));
+            }
+            items.push(ClassItem::Range(member, end));
+        }
+        Ok(Bracket { negated, items })
+    }
+
+    /// Parses an escape inside a bracket expression. Returns the escaped
+    /// character, or `None` after adding a whole class such as `\d` to `items`.
+    fn parse_bracket_escape(
+        &mut self,
+        start: usize,
+        items: &mut Vec<ClassItem>,
+    ) -> Result<Option<char>> {
+        let (_, c) = self.next_or(start, "incomplete escape sequence")?;
+        let item = match c {
+            'd' | 'D' => ClassItem::Perl(PerlClass::Digit, c == 'D'),
+            'w' | 'W' => ClassItem::Perl(PerlClass::Word, c == 'W'),
+            's' | 'S' => ClassItem::Perl(PerlClass::Space, c == 'S'),
+            'p' | 'P' => ClassItem::Unicode(self.parse_unicode_class(start)?, c == 'P'),
+            c => return self.parse_char_escape(start, c).map(Some),
+        };
+        items.push(item);
+        Ok(None)
+    }
+
+    /// Parses `[:name:]` or `[:^name:]` after the opening `[:`.
+    fn parse_posix_class(&mut self, start: usize) -> Result<ClassItem> {
+        let negated = self.eat('^');
+        let mut name = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, ':')) if self.eat(']') => break,
+                Some((_, c)) => name.push(c),
+                None => return Err(self.error(start, "Expected ':]' after POSIX class")),
+            }
+        }
+        Ok(ClassItem::Posix(name, negated))
+    }
+
+    fn parse_group(&mut self, start: usize) -> Result<Ast> {
+        let kind = if self.eat('?') {
+            let (_, c) = self.next_or(start, "Expected group syntax after '(?'")?;
+            match c {
+                ':' => GroupKind::NonCapturing,
+                '>' => GroupKind::Atomic,
+                '=' | '!' => GroupKind::Lookaround {
+                    ahead: true,
+                    negated: c == '!',
+                },
+                '<' if self.eat('=') => GroupKind::Lookaround {
+                    ahead: false,
+                    negated: false,
+                },
+                '<' if self.eat('!') => GroupKind::Lookaround {
+                    ahead: false,
+                    negated: true,
+                },
+                'P' if self.eat('<') => self.capture(start, true)?,
+                '<' => self.capture(start, true)?,
+                c if c.is_ascii_alphabetic() || c == '-' => {
+                    let mut flags = String::from(c);
+                    loop {
+                        match self.chars.next() {
+                            Some((_, ')')) => {
+                                return Ok(self.node(start, AstKind::Flags(flags)));
+                            }
+                            Some((_, ':')) => break GroupKind::Flags(flags),
+                            Some((_, c)) if c.is_ascii_alphabetic() || c == '-' => flags.push(c),
+                            _ => return Err(self.error(start, "invalid group flags")),
+                        }
+                    }
+                }
+                _ => return Err(self.error(start, "unsupported group syntax")),
+            }
+        } else {
+            self.capture(start, false)?
+        };
+
+        let ast = self.parse_alternation()?;
+        if !self.eat(')') {
+            return Err(self.error(start, "unclosed group"));
+        }
+        Ok(self.node(
+            start,
+            AstKind::Group(Group {
+                kind,
+                ast: Box::new(ast),
+            }),
+        ))
+    }
+
+    /// Allocates the next capture index, reading the group's name first when
+    /// `named`. Non-capturing groups leave the numbering unchanged.
+    fn capture(&mut self, start: usize, named: bool) -> Result<GroupKind> {
+        let name = if named {
+            let name = self.parse_group_name(start)?;
+            if self
+                .group_names
+                .iter()
+                .any(|(existing, _)| *existing == name)
+            {
+                return Err(self.error(start, format!("duplicate capture group name: {name}")));
+            }
+            self.group_names.push((name.clone(), self.group_count));
+            Some(name)
+        } else {
+            None
+        };
+        let index = self.group_count;
+        self.group_count += 1;
+        Ok(GroupKind::Capture { index, name })
+    }
+
+    /// Parses a group name up to its closing `>`.
+    fn parse_group_name(&mut self, start: usize) -> Result<String> {
+        let mut name = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '>')) => break,
+                Some((_, c)) if c.is_alphanumeric() || c == '_' => name.push(c),
+                _ => return Err(self.error(start, "invalid capture group name")),
+            }
+        }
+        if name.chars().next().is_none_or(|c| c.is_ascii_digit()) {
+            return Err(self.error(start, format!("invalid capture group name: {name:?}")));
+        }
+        Ok(name)
+    }
+
+    /// Consumes characters up to `close`, which is consumed but not returned.
+    fn take_until(&mut self, close: char, start: usize, message: &str) -> Result<String> {
+        let mut text = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, c)) if c == close => return Ok(text),
+                Some((_, c)) => text.push(c),
+                None => return Err(self.error(start, message)),
+            }
+        }
+    }
+
+    fn next_or(&mut self, start: usize, message: &str) -> Result<(usize, char)> {
+        self.chars.next().ok_or_else(|| self.error(start, message))
+    }
+
+    /// Consumes the peeked character and returns `value`.
+    fn consume<T>(&mut self, value: T) -> T {
+        self.chars.next();
+        value
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        self.chars.next_if(|(_, c)| *c == expected).is_some()
+    }
+
+    fn offset(&mut self) -> usize {
+        self.chars
+            .peek()
+            .map_or(self.pattern.len(), |(offset, _)| *offset)
+    }
+
+    fn node(&mut self, start: usize, kind: AstKind) -> Ast {
+        let end = self.offset();
+        Ast {
+            kind,
+            span: Span { start, end },
+        }
+    }
+
+    /// An error for the pattern text from `start` up to the current position.
+    fn error(&self, start: usize, message: impl std::fmt::Display) -> Error {
+        let end = self
+            .chars
+            .clone()
+            .peek()
+            .map_or(self.pattern.len(), |(offset, _)| *offset)
+            .max(start + 1)
+            .min(self.pattern.len());
+        anyhow!("{message} at {}", Span { start, end })
+    }
+}
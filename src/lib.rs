@@ -1,8 +1,20 @@
+mod archive;
+#[doc(hidden)]
+pub mod color;
+mod decompress;
 mod engine;
 #[doc(hidden)]
+pub mod preprocess;
+#[doc(hidden)]
+pub mod replace;
+mod rewrite;
+#[doc(hidden)]
 pub mod search_runner;
 mod tests;
 
 pub use engine::{
-    compile_regex, find_all_regex_spans_compiled, CompiledRegex, LineCandidate, RegexMatch,
+    compile_regex, compile_regex_with_options, find_all_regex_captures_budgeted,
+    find_all_regex_captures_compiled, find_all_regex_spans_budgeted, find_all_regex_spans_compiled,
+    try_compile_regex_with_options, BacktrackLimit, BacktrackLimitExceeded, CompiledRegex,
+    LineCandidate, RegexCaptures, RegexMatch, RegexOptions, SearchBudget,
 };
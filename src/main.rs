@@ -1,14 +1,19 @@
 use std::{
+    env,
     io::{self, IsTerminal},
     process,
+    time::Duration,
 };
 
 use anyhow::Result;
 use clap::{Parser, ValueEnum};
 
 use grep_rs::{
-    compile_regex,
-    search_runner::{run_search, SearchConfig},
+    color::ColorSpecs,
+    preprocess::Preprocessor,
+    replace::Replacement,
+    search_runner::{run_search, RewriteConfig, SearchConfig},
+    try_compile_regex_with_options, BacktrackLimit, RegexOptions,
 };
 
 #[derive(Clone, Copy, Debug, ValueEnum)]
@@ -19,20 +24,102 @@ enum ColorMode {
 }
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, disable_help_flag = true)]
 struct Args {
+    #[arg(long, action = clap::ArgAction::Help, help = "Print help")]
+    help: Option<bool>,
+
     #[arg(short = 'o')]
     only_matching: bool,
 
-    #[arg(long, value_enum, default_value_t = ColorMode::Never)]
+    #[arg(long, value_enum, default_value_t = ColorMode::Auto)]
     color: ColorMode,
 
+    #[arg(long = "colors", value_name = "spec")]
+    colors: Vec<String>,
+
     #[arg(short = 'E', allow_hyphen_values = true, value_name = "pattern")]
     pattern: String,
 
     #[arg(short = 'r')]
     recursive: bool,
 
+    #[arg(long, value_name = "command", conflicts_with = "in_place")]
+    pre: Option<std::path::PathBuf>,
+
+    #[arg(long, value_name = "glob", requires = "pre")]
+    pre_glob: Vec<String>,
+
+    #[arg(long, conflicts_with = "in_place")]
+    search_zip: bool,
+
+    #[arg(long, conflicts_with = "in_place")]
+    search_archives: bool,
+
+    #[arg(
+        long,
+        default_value_t = 2,
+        value_name = "depth",
+        requires = "search_archives"
+    )]
+    max_archive_depth: usize,
+
+    #[arg(short = 'U', long, conflicts_with = "in_place")]
+    multiline: bool,
+
+    #[arg(long, requires = "multiline")]
+    multiline_dotall: bool,
+
+    #[arg(long, hide = true)]
+    engine_differential: bool,
+
+    /// Backtracking steps allowed per line before a file is reported as too
+    /// complex; 0 removes the limit.
+    #[arg(long, default_value_t = DEFAULT_BACKTRACK_LIMIT, value_name = "steps")]
+    backtrack_limit: u64,
+
+    /// Backtracking steps allowed per file.
+    #[arg(long, value_name = "steps")]
+    backtrack_file_limit: Option<u64>,
+
+    /// Wall-clock milliseconds of backtracking allowed per file.
+    #[arg(long, value_name = "ms")]
+    backtrack_timeout: Option<u64>,
+
+    #[arg(long, allow_hyphen_values = true, value_name = "replacement")]
+    replace: Option<String>,
+
+    #[arg(long, requires = "replace", conflicts_with = "only_matching")]
+    in_place: bool,
+
+    #[arg(
+        long,
+        requires = "in_place",
+        value_name = "suffix",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ".orig"
+    )]
+    backup: Option<String>,
+
+    #[arg(long, requires = "in_place")]
+    dry_run: bool,
+
+    #[arg(long, requires = "in_place")]
+    diff: bool,
+
+    #[arg(short = 'H', long, overrides_with = "no_filename")]
+    with_filename: bool,
+
+    #[arg(short = 'h', long, overrides_with = "with_filename")]
+    no_filename: bool,
+
+    #[arg(short = 'Z', long = "null")]
+    null: bool,
+
+    #[arg(short = 'z', long = "null-data")]
+    null_data: bool,
+
     #[arg(short = 'j', long, default_value_t = default_thread_count(), value_name = "threads")]
     threads: usize,
 
@@ -40,15 +127,70 @@ struct Args {
     files: Vec<std::path::PathBuf>,
 }
 
+const DEFAULT_BACKTRACK_LIMIT: u64 = 10_000_000;
+
 fn default_thread_count() -> usize {
     std::thread::available_parallelism()
         .map(std::num::NonZeroUsize::get)
         .unwrap_or(1)
 }
 
+fn filename_override(args: &Args) -> Option<bool> {
+    if args.with_filename {
+        Some(true)
+    } else if args.no_filename {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+fn color_specs(args: &Args) -> Result<ColorSpecs> {
+    let mut colors = ColorSpecs::default();
+    if let Ok(grep_colors) = env::var("GREP_COLORS") {
+        colors.apply_grep_colors(&grep_colors);
+    }
+    for spec in &args.colors {
+        colors.apply_spec(spec)?;
+    }
+    Ok(colors)
+}
+
+fn backtrack_limit(args: &Args) -> BacktrackLimit {
+    BacktrackLimit {
+        steps_per_line: (args.backtrack_limit > 0).then_some(args.backtrack_limit),
+        steps_per_file: args.backtrack_file_limit,
+        timeout: args.backtrack_timeout.map(Duration::from_millis),
+    }
+}
+
 fn main() -> Result<()> {
     let args = Args::parse();
-    let compiled = compile_regex(&args.pattern);
+    let compiled = match try_compile_regex_with_options(
+        &args.pattern,
+        RegexOptions {
+            multiline: args.multiline,
+            dot_matches_new_line: args.multiline_dotall,
+            differential: args.engine_differential,
+            backtrack_limit: backtrack_limit(&args),
+        },
+    ) {
+        Ok(compiled) => compiled,
+        Err(err) => {
+            eprintln!("grep-rs: {err:#}");
+            process::exit(2);
+        }
+    };
+    let preprocessor = args
+        .pre
+        .as_deref()
+        .map(|command| Preprocessor::new(command, &args.pre_glob))
+        .transpose()?;
+    let replacement = args
+        .replace
+        .as_deref()
+        .map(|template| Replacement::parse(template, &compiled))
+        .transpose()?;
     let use_color = match args.color {
         ColorMode::Always => true,
         ColorMode::Auto => io::stdout().is_terminal(),
@@ -62,7 +204,21 @@ fn main() -> Result<()> {
         SearchConfig {
             only_matching: args.only_matching,
             use_color,
-            show_prefix: false,
+            colors: color_specs(&args)?,
+            show_prefix: filename_override(&args),
+            null_after_name: args.null,
+            line_terminator: if args.null_data { b'\0' } else { b'\n' },
+            multiline: args.multiline,
+            replacement: replacement.as_ref(),
+            rewrite: args.in_place.then_some(RewriteConfig {
+                backup_suffix: args.backup.as_deref(),
+                dry_run: args.dry_run,
+                show_diff: args.diff,
+            }),
+            preprocessor: preprocessor.as_ref(),
+            search_zip: args.search_zip,
+            search_archives: args.search_archives,
+            max_archive_depth: args.max_archive_depth,
             threads: args.threads.max(1),
         },
     )?;
@@ -81,6 +237,82 @@ mod tests {
         assert_eq!(args.threads, 8);
     }
 
+    #[test]
+    fn parses_null_separator_flags() {
+        let args = Args::parse_from(["grep-rust", "-Z", "-z", "-E", "foo", "file.txt"]);
+        assert!(args.null);
+        assert!(args.null_data);
+    }
+
+    #[test]
+    fn last_filename_flag_wins() {
+        let args = Args::parse_from(["grep-rust", "-h", "-H", "-E", "foo", "file.txt"]);
+        assert_eq!(filename_override(&args), Some(true));
+
+        let args = Args::parse_from(["grep-rust", "-H", "-h", "-E", "foo", "file.txt"]);
+        assert_eq!(filename_override(&args), Some(false));
+
+        let args = Args::parse_from(["grep-rust", "-E", "foo", "file.txt"]);
+        assert_eq!(filename_override(&args), None);
+    }
+
+    #[test]
+    fn color_defaults_to_auto() {
+        let args = Args::parse_from(["grep-rust", "-E", "foo"]);
+        assert!(matches!(args.color, ColorMode::Auto));
+    }
+
+    #[test]
+    fn in_place_requires_replacement() {
+        assert!(Args::try_parse_from(["grep-rust", "--in-place", "-E", "foo", "a.txt"]).is_err());
+
+        let args = Args::try_parse_from([
+            "grep-rust",
+            "--replace",
+            "bar",
+            "--in-place",
+            "--backup",
+            "-E",
+            "foo",
+            "a.txt",
+        ])
+        .unwrap();
+        assert_eq!(args.backup.as_deref(), Some(".orig"));
+    }
+
+    #[test]
+    fn backtrack_limit_defaults_to_a_per_line_budget() {
+        let args = Args::parse_from(["grep-rust", "-E", "foo"]);
+        assert_eq!(
+            backtrack_limit(&args),
+            BacktrackLimit {
+                steps_per_line: Some(DEFAULT_BACKTRACK_LIMIT),
+                steps_per_file: None,
+                timeout: None,
+            }
+        );
+
+        let args = Args::parse_from([
+            "grep-rust",
+            "--backtrack-limit",
+            "0",
+            "--backtrack-file-limit",
+            "500",
+            "--backtrack-timeout",
+            "250",
+            "-E",
+            "foo",
+        ]);
+        assert_eq!(
+            backtrack_limit(&args),
+            BacktrackLimit {
+                steps_per_line: None,
+                steps_per_file: Some(500),
+                timeout: Some(Duration::from_millis(250)),
+            }
+        );
+    }
+
     #[test]
     fn default_thread_count_is_non_zero() {
         assert!(default_thread_count() >= 1);
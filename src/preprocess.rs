@@ -0,0 +1,283 @@
+use std::{
+    fmt,
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+};
+
+use anyhow::{bail, Result};
+
+/// A `--pre` command whose stdout is searched instead of the file contents.
+/// The command receives the file path as its only argument and the file on
+/// stdin. With `--pre-glob`, only matching files are preprocessed.
+#[derive(Debug, Clone)]
+pub struct Preprocessor {
+    command: PathBuf,
+    globs: Vec<Glob>,
+}
+
+/// A `--pre` command that could not produce text for one file. The search
+/// reports it and moves on to the next file.
+#[derive(Debug)]
+pub(crate) struct PreprocessorFailed {
+    command: PathBuf,
+    reason: FailureReason,
+}
+
+#[derive(Debug)]
+enum FailureReason {
+    Spawn(io::Error),
+    Exit { status: ExitStatus, stderr: String },
+    InvalidUtf8,
+}
+
+impl fmt::Display for PreprocessorFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let command = self.command.display();
+        match &self.reason {
+            FailureReason::Spawn(err) => write!(f, "failed to run preprocessor {command}: {err}"),
+            FailureReason::Exit { status, stderr } => {
+                write!(f, "preprocessor {command} failed ({status})")?;
+                if !stderr.is_empty() {
+                    write!(f, ": {stderr}")?;
+                }
+                Ok(())
+            }
+            FailureReason::InvalidUtf8 => {
+                write!(f, "preprocessor {command} produced invalid UTF-8")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PreprocessorFailed {}
+
+/// A shell-style glob. Patterns without `/` match the file name, patterns with
+/// `/` match the whole path. `*` and `?` do not cross `/`, `**` does, and
+/// `[...]`, `[!...]` and `{a,b}` behave as in the shell.
+#[derive(Debug, Clone)]
+struct Glob {
+    tokens: Vec<GlobToken>,
+    match_file_name: bool,
+}
+
+#[derive(Debug, Clone)]
+enum GlobToken {
+    Literal(char),
+    AnyChar,
+    AnyRun,
+    AnyPath,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+    Alternatives(Vec<Vec<GlobToken>>),
+}
+
+impl Preprocessor {
+    pub fn new(command: impl Into<PathBuf>, globs: &[String]) -> Result<Self> {
+        Ok(Self {
+            command: command.into(),
+            globs: globs
+                .iter()
+                .map(|glob| Glob::new(glob))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    #[must_use]
+    pub fn applies_to(&self, path: &Path) -> bool {
+        self.globs.is_empty() || self.globs.iter().any(|glob| glob.is_match(path))
+    }
+
+    pub(crate) fn run(&self, path: &Path) -> Result<String> {
+        let stdin = File::open(path)?;
+        let output = Command::new(&self.command)
+            .arg(path)
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .map_err(|err| self.failed(FailureReason::Spawn(err)))?;
+
+        if !output.status.success() {
+            return Err(self.failed(FailureReason::Exit {
+                status: output.status,
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            }));
+        }
+
+        String::from_utf8(output.stdout).map_err(|_| self.failed(FailureReason::InvalidUtf8))
+    }
+
+    fn failed(&self, reason: FailureReason) -> anyhow::Error {
+        PreprocessorFailed {
+            command: self.command.clone(),
+            reason,
+        }
+        .into()
+    }
+}
+
+impl Glob {
+    fn new(pattern: &str) -> Result<Self> {
+        let mut chars = pattern.chars().peekable();
+        let tokens = parse_glob_tokens(&mut chars, false)?;
+        Ok(Self {
+            tokens,
+            match_file_name: !pattern.contains('/'),
+        })
+    }
+
+    fn is_match(&self, path: &Path) -> bool {
+        let text = if self.match_file_name {
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        } else {
+            path.to_string_lossy().into_owned()
+        };
+        let chars = text.chars().collect::<Vec<_>>();
+        match_tokens(&self.tokens, &chars)
+    }
+}
+
+fn parse_glob_tokens(
+    chars: &mut std::iter::Peekable<std::str::Chars<'_>>,
+    in_alternative: bool,
+) -> Result<Vec<GlobToken>> {
+    let mut tokens = Vec::new();
+    while let Some(&ch) = chars.peek() {
+        if in_alternative && (ch == ',' || ch == '}') {
+            break;
+        }
+        chars.next();
+        let token = match ch {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                GlobToken::AnyPath
+            }
+            '*' => GlobToken::AnyRun,
+            '?' => GlobToken::AnyChar,
+            '[' => parse_glob_class(chars)?,
+            '{' => {
+                let mut alternatives = Vec::new();
+                loop {
+                    alternatives.push(parse_glob_tokens(chars, true)?);
+                    match chars.next() {
+                        Some(',') => continue,
+                        Some('}') => break,
+                        _ => bail!("unclosed '{{' in glob"),
+                    }
+                }
+                GlobToken::Alternatives(alternatives)
+            }
+            '\\' => GlobToken::Literal(chars.next().unwrap_or('\\')),
+            other => GlobToken::Literal(other),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn parse_glob_class(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> Result<GlobToken> {
+    let negated = matches!(chars.peek(), Some('!' | '^'));
+    if negated {
+        chars.next();
+    }
+
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+        let Some(start) = chars.next() else {
+            bail!("unclosed '[' in glob");
+        };
+        if start == ']' && !first {
+            break;
+        }
+        first = false;
+
+        let mut lookahead = chars.clone();
+        if lookahead.next() == Some('-') && lookahead.peek().is_some_and(|end| *end != ']') {
+            chars.next();
+            let end = chars.next().expect("range end was peeked");
+            ranges.push((start, end));
+        } else {
+            ranges.push((start, start));
+        }
+    }
+
+    Ok(GlobToken::Class { negated, ranges })
+}
+
+fn match_tokens(tokens: &[GlobToken], text: &[char]) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return text.is_empty();
+    };
+
+    match token {
+        GlobToken::Literal(expected) => {
+            text.first() == Some(expected) && match_tokens(rest, &text[1..])
+        }
+        GlobToken::AnyChar => {
+            text.first().is_some_and(|ch| *ch != '/') && match_tokens(rest, &text[1..])
+        }
+        GlobToken::Class { negated, ranges } => {
+            text.first().is_some_and(|ch| {
+                *ch != '/'
+                    && ranges
+                        .iter()
+                        .any(|(start, end)| (*start..=*end).contains(ch))
+                        != *negated
+            }) && match_tokens(rest, &text[1..])
+        }
+        GlobToken::AnyRun => {
+            let run = text.iter().take_while(|ch| **ch != '/').count();
+            (0..=run).any(|skip| match_tokens(rest, &text[skip..]))
+        }
+        GlobToken::AnyPath => (0..=text.len()).any(|skip| match_tokens(rest, &text[skip..])),
+        GlobToken::Alternatives(alternatives) => alternatives.iter().any(|alternative| {
+            let combined = alternative
+                .iter()
+                .chain(rest.iter())
+                .cloned()
+                .collect::<Vec<_>>();
+            match_tokens(&combined, text)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(glob: &str, path: &str) -> bool {
+        Glob::new(glob).unwrap().is_match(Path::new(path))
+    }
+
+    #[test]
+    fn file_name_globs_ignore_directories() {
+        assert!(matches("*.pb", "dumps/nested/message.pb"));
+        assert!(matches("config.{enc,gpg}", "etc/config.gpg"));
+        assert!(matches("log-[0-9]?.txt", "var/log-42.txt"));
+        assert!(!matches("*.pb", "dumps/message.pbtxt"));
+        assert!(!matches("log-[!0-9]*", "log-1.txt"));
+    }
+
+    #[test]
+    fn path_globs_only_cross_directories_with_double_star() {
+        assert!(matches("secrets/*.enc", "secrets/db.enc"));
+        assert!(!matches("secrets/*.enc", "secrets/nested/db.enc"));
+        assert!(matches("secrets/**/*.enc", "secrets/nested/db.enc"));
+    }
+
+    #[test]
+    fn preprocessor_without_globs_applies_everywhere() {
+        let preprocessor = Preprocessor::new("cat", &[]).unwrap();
+        assert!(preprocessor.applies_to(Path::new("any/file.txt")));
+
+        let preprocessor = Preprocessor::new("cat", &["*.gz".to_string()]).unwrap();
+        assert!(!preprocessor.applies_to(Path::new("any/file.txt")));
+    }
+}
@@ -0,0 +1,182 @@
+use anyhow::{anyhow, bail, Result};
+
+use crate::{CompiledRegex, RegexCaptures};
+
+/// A `--replace` template compiled against a regex. `$N` and `${N}` expand to
+/// capture group `N`, `$name` and `${name}` to a named group, and `$$` to a
+/// literal `$`. Groups that did not participate in a match expand to nothing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replacement {
+    parts: Vec<ReplacementPart>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ReplacementPart {
+    Literal(String),
+    Group(usize),
+}
+
+impl Replacement {
+    pub fn parse(template: &str, regex: &CompiledRegex) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.char_indices().peekable();
+
+        while let Some((_, ch)) = chars.next() {
+            if ch != '$' {
+                literal.push(ch);
+                continue;
+            }
+
+            let reference = match chars.peek().map(|&(_, next)| next) {
+                Some('$') => {
+                    chars.next();
+                    literal.push('$');
+                    continue;
+                }
+                Some('{') => {
+                    chars.next();
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some((_, '}')) => break,
+                            Some((_, ch)) => name.push(ch),
+                            None => bail!("unclosed '${{' in replacement '{template}'"),
+                        }
+                    }
+                    name
+                }
+                Some(next) if next.is_ascii_digit() => {
+                    take_while(&mut chars, |ch| ch.is_ascii_digit())
+                }
+                Some(next) if next.is_ascii_alphabetic() || next == '_' => {
+                    take_while(&mut chars, |ch| ch.is_ascii_alphanumeric() || ch == '_')
+                }
+                _ => {
+                    literal.push('$');
+                    continue;
+                }
+            };
+
+            if !literal.is_empty() {
+                parts.push(ReplacementPart::Literal(std::mem::take(&mut literal)));
+            }
+            parts.push(ReplacementPart::Group(resolve_group(&reference, regex)?));
+        }
+
+        if !literal.is_empty() {
+            parts.push(ReplacementPart::Literal(literal));
+        }
+
+        Ok(Self { parts })
+    }
+
+    pub fn expand(&self, line: &str, captures: &RegexCaptures, output: &mut String) {
+        for part in &self.parts {
+            match part {
+                ReplacementPart::Literal(text) => output.push_str(text),
+                ReplacementPart::Group(idx) => {
+                    if let Some(span) = captures.get(*idx) {
+                        output.push_str(&line[span.start..span.end]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn take_while(
+    chars: &mut std::iter::Peekable<std::str::CharIndices<'_>>,
+    pred: impl Fn(char) -> bool,
+) -> String {
+    let mut taken = String::new();
+    while let Some(&(_, ch)) = chars.peek() {
+        if !pred(ch) {
+            break;
+        }
+        taken.push(ch);
+        chars.next();
+    }
+    taken
+}
+
+fn resolve_group(reference: &str, regex: &CompiledRegex) -> Result<usize> {
+    if reference.is_empty() {
+        bail!("empty group reference '${{}}' in replacement");
+    }
+
+    if reference.chars().all(|ch| ch.is_ascii_digit()) {
+        let idx: usize = reference.parse()?;
+        if idx >= regex.captures_len() {
+            bail!(
+                "replacement refers to group {idx}, but the pattern has {} group(s)",
+                regex.captures_len() - 1
+            );
+        }
+        return Ok(idx);
+    }
+
+    regex
+        .capture_index(reference)
+        .ok_or_else(|| anyhow!("replacement refers to unknown group '{reference}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile_regex, find_all_regex_captures_compiled};
+
+    fn replace_all(line: &str, regex: &str, template: &str) -> Vec<String> {
+        let compiled = compile_regex(regex);
+        let replacement = Replacement::parse(template, &compiled).unwrap();
+        find_all_regex_captures_compiled(line, &compiled)
+            .iter()
+            .map(|captures| {
+                let mut expanded = String::new();
+                replacement.expand(line, captures, &mut expanded);
+                expanded
+            })
+            .collect()
+    }
+
+    #[test]
+    fn expands_numbered_groups_across_engines() {
+        assert_eq!(
+            replace_all("fn foo() fn bar()", r"fn (\w+)\(\)", "fn $1_new() /* $0 */"),
+            vec!["fn foo_new() /* fn foo() */", "fn bar_new() /* fn bar() */"]
+        );
+        assert_eq!(
+            replace_all("cat and cat", r"(\w+) and \1", "${1}s"),
+            vec!["cats"]
+        );
+        assert_eq!(
+            replace_all("cat dog", "(cat|dog)", "<$1>"),
+            vec!["<cat>", "<dog>"]
+        );
+    }
+
+    #[test]
+    fn expands_named_groups_and_escaped_dollars() {
+        assert_eq!(
+            replace_all(
+                "price=42",
+                r"price=(?P<amount>\d+)",
+                "$$${amount}.00 ($amount)"
+            ),
+            vec!["$42.00 (42)"]
+        );
+    }
+
+    #[test]
+    fn unmatched_optional_groups_expand_to_nothing() {
+        assert_eq!(replace_all("ab", r"a(x)?b", "[$1]"), vec!["[]"]);
+    }
+
+    #[test]
+    fn rejects_references_to_missing_groups() {
+        let compiled = compile_regex(r"(\w+)");
+        assert!(Replacement::parse("$2", &compiled).is_err());
+        assert!(Replacement::parse("${missing}", &compiled).is_err());
+        assert!(Replacement::parse("${1", &compiled).is_err());
+    }
+}
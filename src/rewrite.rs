@@ -0,0 +1,214 @@
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow::{Context, Result};
+
+use crate::{find_all_regex_captures_budgeted, replace::Replacement, CompiledRegex};
+
+const DIFF_CONTEXT_LINES: usize = 3;
+
+static TEMP_FILE_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Result of applying a replacement to a whole file.
+pub(crate) struct Rewrite {
+    pub(crate) text: String,
+    pub(crate) matched_lines: usize,
+    records: Vec<RecordChange>,
+}
+
+struct RecordChange {
+    index: usize,
+    replaced: String,
+}
+
+pub(crate) fn rewrite_content(
+    input: &str,
+    pattern: &CompiledRegex,
+    replacement: &Replacement,
+    line_terminator: u8,
+) -> Result<Rewrite> {
+    let budget = pattern.search_budget();
+    let mut text = String::with_capacity(input.len());
+    let mut records = Vec::new();
+
+    for (index, record) in input
+        .split_inclusive(char::from(line_terminator))
+        .enumerate()
+    {
+        let line = crate::search_runner::strip_line_terminator(record, line_terminator);
+        let captures = find_all_regex_captures_budgeted(line, pattern, &budget)?;
+        if captures.is_empty() {
+            text.push_str(record);
+            continue;
+        }
+
+        let record_start = text.len();
+        let mut last = 0;
+        for captured in &captures {
+            let whole = captured.whole();
+            text.push_str(&line[last..whole.start]);
+            replacement.expand(line, captured, &mut text);
+            last = whole.end;
+        }
+        text.push_str(&record[last..]);
+        records.push(RecordChange {
+            index,
+            replaced: text[record_start..].to_string(),
+        });
+    }
+
+    Ok(Rewrite {
+        text,
+        matched_lines: records.len(),
+        records,
+    })
+}
+
+impl Rewrite {
+    /// Renders a unified diff between `original` and the rewritten text.
+    /// Replacements never add or remove records, so hunks are built from the
+    /// changed record indices instead of a general line diff.
+    pub(crate) fn write_unified_diff(
+        &self,
+        output: &mut Vec<u8>,
+        path: &Path,
+        original: &str,
+        line_terminator: u8,
+    ) {
+        if self.records.is_empty() {
+            return;
+        }
+
+        let old_records = original
+            .split_inclusive(char::from(line_terminator))
+            .collect::<Vec<_>>();
+        let mut new_records = old_records.clone();
+        for change in &self.records {
+            new_records[change.index] = &change.replaced;
+        }
+
+        let path = path.to_string_lossy();
+        output.extend_from_slice(format!("--- {path}\n+++ {path}\n").as_bytes());
+
+        let mut line_offset = 0isize;
+        let mut group_start = 0;
+        while group_start < self.records.len() {
+            let mut group_end = group_start + 1;
+            while group_end < self.records.len()
+                && self.records[group_end].index - self.records[group_end - 1].index
+                    <= DIFF_CONTEXT_LINES * 2 + 1
+            {
+                group_end += 1;
+            }
+
+            let first = self.records[group_start].index;
+            let last = self.records[group_end - 1].index;
+            let hunk_start = first.saturating_sub(DIFF_CONTEXT_LINES);
+            let hunk_end = (last + DIFF_CONTEXT_LINES + 1).min(old_records.len());
+
+            let mut body = Vec::new();
+            let mut old_len = 0;
+            let mut new_len = 0;
+            for index in hunk_start..hunk_end {
+                let old = old_records[index];
+                let new = new_records[index];
+                if old == new {
+                    push_diff_lines(&mut body, b' ', old, line_terminator);
+                    old_len += 1;
+                    new_len += 1;
+                } else {
+                    old_len += push_diff_lines(&mut body, b'-', old, line_terminator);
+                    new_len += push_diff_lines(&mut body, b'+', new, line_terminator);
+                }
+            }
+
+            let old_start = hunk_start + 1;
+            let new_start = old_start.saturating_add_signed(line_offset);
+            output.extend_from_slice(
+                format!("@@ -{old_start},{old_len} +{new_start},{new_len} @@\n").as_bytes(),
+            );
+            output.extend_from_slice(&body);
+            line_offset += new_len as isize - old_len as isize;
+            group_start = group_end;
+        }
+    }
+}
+
+/// Writes `record` as diff lines tagged with `marker` and returns how many
+/// lines it produced. A replacement may introduce newlines, so one record can
+/// span several diff lines.
+fn push_diff_lines(output: &mut Vec<u8>, marker: u8, record: &str, line_terminator: u8) -> usize {
+    let terminated = record.ends_with(char::from(line_terminator));
+    let body = if terminated {
+        &record[..record.len() - 1]
+    } else {
+        record
+    };
+
+    let mut count = 0;
+    for line in body.split('\n') {
+        output.push(marker);
+        output.extend_from_slice(line.as_bytes());
+        output.push(b'\n');
+        count += 1;
+    }
+    if !terminated {
+        output.extend_from_slice(b"\\ No newline at end of file\n");
+    }
+    count
+}
+
+/// Replaces `path` with `contents` through a temporary file in the same
+/// directory followed by a rename, optionally keeping a copy of the original
+/// at `path` + `backup_suffix`. A symlink is followed, so the file it points
+/// to is rewritten and the link itself is kept.
+pub(crate) fn write_atomically(
+    path: &Path,
+    contents: &str,
+    backup_suffix: Option<&str>,
+) -> Result<()> {
+    let target =
+        fs::canonicalize(path).with_context(|| format!("failed to resolve {}", path.display()))?;
+    let metadata = fs::metadata(&target)?;
+    let temp_path = temp_path_for(&target);
+
+    let write_temp = || -> Result<()> {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.set_permissions(metadata.permissions())?;
+        file.sync_all()?;
+        Ok(())
+    };
+    if let Err(err) = write_temp() {
+        let _ = fs::remove_file(&temp_path);
+        return Err(err).with_context(|| format!("failed to write {}", temp_path.display()));
+    }
+
+    if let Some(suffix) = backup_suffix {
+        let mut backup = path.as_os_str().to_os_string();
+        backup.push(suffix);
+        if let Err(err) = fs::copy(path, &backup) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(err).with_context(|| format!("failed to back up {}", path.display()));
+        }
+    }
+
+    if let Err(err) = fs::rename(&temp_path, &target) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(err).with_context(|| format!("failed to replace {}", path.display()));
+    }
+    Ok(())
+}
+
+fn temp_path_for(path: &Path) -> PathBuf {
+    let id = TEMP_FILE_ID.fetch_add(1, Ordering::Relaxed);
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{name}.grep-rs-{}-{id}.tmp", std::process::id()))
+}
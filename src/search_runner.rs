@@ -1,7 +1,7 @@
 use std::{
     collections::BTreeMap,
     fs::File,
-    io::{self, BufWriter, Read, Write},
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
     thread,
 };
@@ -10,25 +10,73 @@ use anyhow::{bail, Context, Result};
 use crossbeam_channel::{bounded, unbounded, Receiver};
 use memmap2::Mmap;
 
-use crate::{find_all_regex_spans_compiled, CompiledRegex, LineCandidate, RegexMatch};
+use crate::{
+    archive::{
+        is_archive, read_archive, ArchiveLimits, ArchiveMember, MemberData, UnreadableArchive,
+    },
+    color::{ColorSpecs, Style},
+    decompress::{
+        decompress_file, CompressionFormat, DecompressedTooLarge, InvalidCompressedData, MAGIC_LEN,
+    },
+    find_all_regex_captures_budgeted, find_all_regex_spans_budgeted,
+    preprocess::{Preprocessor, PreprocessorFailed},
+    replace::Replacement,
+    rewrite::{rewrite_content, write_atomically},
+    BacktrackLimitExceeded, CompiledRegex, LineCandidate, RegexCaptures, RegexMatch, SearchBudget,
+};
 
-const ANSI_BOLD_RED: &[u8] = b"\x1b[1;31m";
-const ANSI_RESET: &[u8] = b"\x1b[0m";
+const STDIN_LABEL: &str = "(standard input)";
 const JOB_CHANNEL_BOUND: usize = 64;
 const MMAP_THRESHOLD_BYTES: u64 = 1 << 20;
 
 #[derive(Clone, Copy, Debug)]
-pub struct SearchConfig {
+pub struct SearchConfig<'a> {
     pub only_matching: bool,
     pub use_color: bool,
-    pub show_prefix: bool,
+    pub colors: ColorSpecs,
+    pub show_prefix: Option<bool>,
+    pub null_after_name: bool,
+    pub line_terminator: u8,
+    pub multiline: bool,
+    pub replacement: Option<&'a Replacement>,
+    pub rewrite: Option<RewriteConfig<'a>>,
+    pub preprocessor: Option<&'a Preprocessor>,
+    pub search_zip: bool,
+    pub search_archives: bool,
+    pub max_archive_depth: usize,
     pub threads: usize,
 }
 
+/// Applies `replacement` to the searched files instead of printing matches.
+#[derive(Clone, Copy, Debug)]
+pub struct RewriteConfig<'a> {
+    pub backup_suffix: Option<&'a str>,
+    pub dry_run: bool,
+    pub show_diff: bool,
+}
+
+/// Something to search: a file on disk, an archive whose members are read
+/// by the worker that searches it, or one of those members.
+#[derive(Debug, Clone)]
+pub(crate) enum SearchTarget {
+    File(PathBuf),
+    Archive(PathBuf, ArchiveLimits),
+    Member(ArchiveMember),
+}
+
+impl SearchTarget {
+    fn path(&self) -> &Path {
+        match self {
+            Self::File(path) | Self::Archive(path, _) => path,
+            Self::Member(member) => &member.label,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 struct FileJob {
     sequence_no: usize,
-    path: PathBuf,
+    target: SearchTarget,
 }
 
 #[derive(Debug)]
@@ -36,21 +84,24 @@ struct FileResult {
     sequence_no: usize,
     match_count: usize,
     rendered_output: Vec<u8>,
+    diagnostics: Vec<String>,
     error: Option<String>,
 }
 
 enum FileContent {
     Owned(String),
     Mapped(Mmap),
+    Member(MemberData),
 }
 
 impl FileContent {
     fn as_str(&self, path: &Path) -> Result<&str> {
-        match self {
-            Self::Owned(text) => Ok(text),
-            Self::Mapped(mmap) => std::str::from_utf8(mmap)
-                .with_context(|| format!("{} is not valid UTF-8", path.display())),
-        }
+        let bytes = match self {
+            Self::Owned(text) => return Ok(text),
+            Self::Mapped(mmap) => mmap,
+            Self::Member(member) => member.as_bytes(),
+        };
+        std::str::from_utf8(bytes).with_context(|| format!("{} is not valid UTF-8", path.display()))
     }
 }
 
@@ -58,11 +109,14 @@ impl FileContent {
 struct WorkerBuffers {
     text: String,
     output: Vec<u8>,
+    /// Archive members that were skipped, reported after the archive's output.
+    diagnostics: Vec<String>,
 }
 
 impl WorkerBuffers {
     fn reset_output(&mut self) {
         self.output.clear();
+        self.diagnostics.clear();
     }
 }
 
@@ -71,7 +125,7 @@ pub fn run_search(
     files: &[PathBuf],
     recursive: bool,
     compiled: &CompiledRegex,
-    config: SearchConfig,
+    config: SearchConfig<'_>,
 ) -> Result<usize> {
     let stdout = io::stdout();
     let mut writer = BufWriter::new(stdout.lock());
@@ -84,25 +138,41 @@ pub fn run_search_to_writer<W: Write>(
     files: &[PathBuf],
     recursive: bool,
     compiled: &CompiledRegex,
-    config: SearchConfig,
+    config: SearchConfig<'_>,
 ) -> Result<usize> {
     let match_count = if files.is_empty() {
+        if config.rewrite.is_some() {
+            bail!("cannot rewrite standard input in place");
+        }
         let mut input = String::new();
         io::stdin().read_to_string(&mut input)?;
         let mut output = Vec::new();
-        let total = search_text_content(&input, compiled, &config, "", &mut output);
-        writer.write_all(&output)?;
-        total
+        let prefix = display_prefix(Path::new(STDIN_LABEL), &config);
+        match search_text_content(&input, compiled, &config, &prefix, &mut output) {
+            Ok(total) => {
+                writer.write_all(&output)?;
+                total
+            }
+            Err(err) => {
+                report_skipped(err.context(STDIN_LABEL))?;
+                0
+            }
+        }
     } else {
-        let file_paths = collect_files(files, recursive)?;
+        let targets = collect_files(files, recursive, archive_limits(&config))?;
+        // Archive members are labelled even when the archive is the only file.
+        let many = targets.len() > 1
+            || targets
+                .iter()
+                .any(|target| matches!(target, SearchTarget::Archive(..)));
         let run_config = SearchConfig {
-            show_prefix: file_paths.len() > 1,
+            show_prefix: Some(config.show_prefix.unwrap_or(many)),
             ..config
         };
-        if file_paths.len() > 1 && run_config.threads > 1 {
-            run_files_parallel(writer, file_paths, compiled, run_config)?
+        if targets.len() > 1 && run_config.threads > 1 {
+            run_files_parallel(writer, targets, compiled, run_config)?
         } else {
-            run_files_serial(writer, &file_paths, compiled, &run_config)?
+            run_files_serial(writer, &targets, compiled, &run_config)?
         }
     };
 
@@ -112,26 +182,25 @@ pub fn run_search_to_writer<W: Write>(
 
 fn run_files_serial<W: Write>(
     writer: &mut W,
-    file_paths: &[PathBuf],
+    targets: &[SearchTarget],
     compiled: &CompiledRegex,
-    config: &SearchConfig,
+    config: &SearchConfig<'_>,
 ) -> Result<usize> {
     let mut total = 0;
     let mut buffers = WorkerBuffers::default();
 
-    for path in file_paths {
-        let prefix = display_prefix(path, config.show_prefix);
+    for target in targets {
         buffers.reset_output();
-        let content = load_file_content(path, &mut buffers.text)?;
-        let match_count = search_text_content(
-            content.as_str(path)?,
-            compiled,
-            config,
-            &prefix,
-            &mut buffers.output,
-        );
-        writer.write_all(&buffers.output)?;
-        total += match_count;
+        match search_file(target, &mut buffers, compiled, config) {
+            Ok(match_count) => {
+                writer.write_all(&buffers.output)?;
+                total += match_count;
+            }
+            Err(err) => report_skipped(err)?,
+        }
+        for diagnostic in buffers.diagnostics.drain(..) {
+            eprintln!("{diagnostic}");
+        }
     }
 
     Ok(total)
@@ -139,11 +208,11 @@ fn run_files_serial<W: Write>(
 
 fn run_files_parallel<W: Write>(
     writer: &mut W,
-    file_paths: Vec<PathBuf>,
+    targets: Vec<SearchTarget>,
     compiled: &CompiledRegex,
-    config: SearchConfig,
+    config: SearchConfig<'_>,
 ) -> Result<usize> {
-    let thread_count = config.threads.min(file_paths.len()).max(1);
+    let thread_count = config.threads.min(targets.len()).max(1);
     let (job_tx, job_rx) = bounded::<FileJob>(JOB_CHANNEL_BOUND);
     let (result_tx, result_rx) = unbounded::<FileResult>();
     thread::scope(|scope| -> Result<usize> {
@@ -154,8 +223,11 @@ fn run_files_parallel<W: Write>(
         }
         drop(result_tx);
 
-        for (sequence_no, path) in file_paths.into_iter().enumerate() {
-            job_tx.send(FileJob { sequence_no, path })?;
+        for (sequence_no, target) in targets.into_iter().enumerate() {
+            job_tx.send(FileJob {
+                sequence_no,
+                target,
+            })?;
         }
         drop(job_tx);
 
@@ -169,6 +241,9 @@ fn run_files_parallel<W: Write>(
             while let Some(result) = pending.remove(&next_sequence) {
                 writer.write_all(&result.rendered_output)?;
                 total += result.match_count;
+                for diagnostic in result.diagnostics {
+                    eprintln!("{diagnostic}");
+                }
                 if let Some(error) = result.error {
                     return Err(anyhow::anyhow!(error));
                 }
@@ -184,31 +259,35 @@ fn worker_loop(
     job_rx: Receiver<FileJob>,
     result_tx: crossbeam_channel::Sender<FileResult>,
     compiled: &CompiledRegex,
-    config: SearchConfig,
+    config: SearchConfig<'_>,
 ) {
     let mut buffers = WorkerBuffers::default();
     for job in job_rx.iter() {
         buffers.reset_output();
-        let result = match load_file_content(&job.path, &mut buffers.text).and_then(|content| {
-            search_text_content(
-                content.as_str(&job.path)?,
-                compiled,
-                &config,
-                &display_prefix(&job.path, config.show_prefix),
-                &mut buffers.output,
-            )
-            .pipe(Ok)
-        }) {
+        let result = match search_file(&job.target, &mut buffers, compiled, &config) {
             Ok(match_count) => FileResult {
                 sequence_no: job.sequence_no,
                 match_count,
                 rendered_output: std::mem::take(&mut buffers.output),
+                diagnostics: std::mem::take(&mut buffers.diagnostics),
                 error: None,
             },
+            Err(err) if is_skippable(&err) => {
+                let mut diagnostics = std::mem::take(&mut buffers.diagnostics);
+                diagnostics.push(format!("{err:#}"));
+                FileResult {
+                    sequence_no: job.sequence_no,
+                    match_count: 0,
+                    rendered_output: Vec::new(),
+                    diagnostics,
+                    error: None,
+                }
+            }
             Err(err) => FileResult {
                 sequence_no: job.sequence_no,
                 match_count: 0,
                 rendered_output: Vec::new(),
+                diagnostics: Vec::new(),
                 error: Some(format!("{err:#}")),
             },
         };
@@ -218,10 +297,138 @@ fn worker_loop(
     }
 }
 
-fn load_file_content(path: &Path, reusable_text: &mut String) -> Result<FileContent> {
-    let file = File::open(path)?;
+fn search_file(
+    target: &SearchTarget,
+    buffers: &mut WorkerBuffers,
+    compiled: &CompiledRegex,
+    config: &SearchConfig<'_>,
+) -> Result<usize> {
+    let path = target.path();
+    let content = match target {
+        SearchTarget::File(path) => load_file_content(path, &mut buffers.text, config)
+            .with_context(|| path.display().to_string())?,
+        SearchTarget::Archive(path, limits) => {
+            return search_archive(path, *limits, buffers, compiled, config);
+        }
+        SearchTarget::Member(member) => FileContent::Member(member.data.clone()),
+    };
+    let text = match content.as_str(path) {
+        Ok(text) => text,
+        // Archives routinely bundle binaries, so those members are skipped
+        // instead of failing the whole search.
+        Err(_) if matches!(content, FileContent::Member(_)) => return Ok(0),
+        Err(err) => return Err(err),
+    };
+
+    if let (Some(rewrite), Some(replacement)) = (config.rewrite, config.replacement) {
+        if matches!(target, SearchTarget::Member(_)) {
+            bail!(
+                "{}: cannot rewrite archive members in place",
+                path.display()
+            );
+        }
+        let rewritten = rewrite_content(text, compiled, replacement, config.line_terminator)
+            .with_context(|| path.display().to_string())?;
+        if rewritten.matched_lines == 0 {
+            return Ok(0);
+        }
+        if rewrite.show_diff {
+            rewritten.write_unified_diff(&mut buffers.output, path, text, config.line_terminator);
+        }
+        if !rewrite.dry_run {
+            drop(content);
+            write_atomically(path, &rewritten.text, rewrite.backup_suffix)?;
+        }
+        return Ok(rewritten.matched_lines);
+    }
+
+    search_text_content(
+        text,
+        compiled,
+        config,
+        &display_prefix(path, config),
+        &mut buffers.output,
+    )
+    .with_context(|| path.display().to_string())
+}
+
+/// Searches every member of the archive at `path`, reporting members that
+/// can be skipped in `buffers.diagnostics`.
+fn search_archive(
+    path: &Path,
+    limits: ArchiveLimits,
+    buffers: &mut WorkerBuffers,
+    compiled: &CompiledRegex,
+    config: &SearchConfig<'_>,
+) -> Result<usize> {
+    let mut total = 0;
+    for member in read_archive(path, limits)? {
+        match search_file(&SearchTarget::Member(member), buffers, compiled, config) {
+            Ok(match_count) => total += match_count,
+            Err(err) if is_skippable(&err) => buffers.diagnostics.push(format!("{err:#}")),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(total)
+}
+
+/// Whether a file that failed with `err` should be reported and skipped
+/// rather than failing the whole search.
+fn is_skippable(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<BacktrackLimitExceeded>().is_some()
+        || err.downcast_ref::<UnreadableArchive>().is_some()
+        || err.downcast_ref::<PreprocessorFailed>().is_some()
+        || err.downcast_ref::<DecompressedTooLarge>().is_some()
+        || err.downcast_ref::<InvalidCompressedData>().is_some()
+}
+
+/// Prints `err` as a diagnostic when the file it came from can be skipped,
+/// and returns it otherwise.
+fn report_skipped(err: anyhow::Error) -> Result<()> {
+    if !is_skippable(&err) {
+        return Err(err);
+    }
+    eprintln!("{err:#}");
+    Ok(())
+}
+
+fn load_file_content(
+    path: &Path,
+    reusable_text: &mut String,
+    config: &SearchConfig<'_>,
+) -> Result<FileContent> {
+    if let Some(preprocessor) = config.preprocessor.filter(|pre| pre.applies_to(path)) {
+        return preprocessor.run(path).map(FileContent::Owned);
+    }
+
+    let mut file = File::open(path)?;
     let metadata = file.metadata()?;
 
+    if config.search_zip {
+        let mut header = [0; MAGIC_LEN];
+        let header_len = read_header(&mut file, &mut header)?;
+        if let Some(format) = CompressionFormat::detect(&header[..header_len]) {
+            let decompressed = match decompress_file(format, &file) {
+                Ok(decompressed) => decompressed,
+                // Plain text can start with magic bytes too, such as `BZh`,
+                // so a file that does not decompress is searched as it is.
+                Err(err) if !err.is::<DecompressedTooLarge>() => {
+                    let mut raw = Vec::new();
+                    file.seek(SeekFrom::Start(0))?;
+                    file.read_to_end(&mut raw)?;
+                    return String::from_utf8(raw)
+                        .map(FileContent::Owned)
+                        .map_err(|_| err);
+                }
+                Err(err) => return Err(err),
+            };
+            return String::from_utf8(decompressed)
+                .map(FileContent::Owned)
+                .context("decompressed contents are not valid UTF-8");
+        }
+        file.seek(SeekFrom::Start(0))?;
+    }
+
     if should_mmap(&metadata) {
         if let Ok(mmap) = unsafe { Mmap::map(&file) } {
             return Ok(FileContent::Mapped(mmap));
@@ -229,32 +436,62 @@ fn load_file_content(path: &Path, reusable_text: &mut String) -> Result<FileCont
     }
 
     reusable_text.clear();
-    let mut file = file;
     file.read_to_string(reusable_text)?;
     Ok(FileContent::Owned(reusable_text.clone()))
 }
 
+/// Fills as much of `header` as the file allows; short files are not an error.
+fn read_header(file: &mut File, header: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < header.len() {
+        match file.read(&mut header[filled..])? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
+
 fn should_mmap(metadata: &std::fs::Metadata) -> bool {
     metadata.len() >= MMAP_THRESHOLD_BYTES && cfg!(not(target_os = "macos"))
 }
 
-fn display_prefix(path: &Path, show_prefix: bool) -> String {
-    if !show_prefix {
-        String::new()
+fn display_prefix(path: &Path, config: &SearchConfig<'_>) -> Vec<u8> {
+    let mut prefix = Vec::new();
+    if config.show_prefix != Some(true) {
+        return prefix;
+    }
+
+    let path = path.to_string_lossy();
+    let (path_style, separator_style) = if config.use_color {
+        (config.colors.path, config.colors.separator)
+    } else {
+        (Style::default(), Style::default())
+    };
+    path_style
+        .write_styled(&mut prefix, path.as_bytes())
+        .expect("writing to vec should not fail");
+    if config.null_after_name {
+        prefix.push(b'\0');
     } else {
-        format!("{}:", path.to_string_lossy())
+        separator_style
+            .write_styled(&mut prefix, b":")
+            .expect("writing to vec should not fail");
     }
+    prefix
 }
 
 #[doc(hidden)]
 pub fn search_text_content(
     input: &str,
     pattern: &CompiledRegex,
-    config: &SearchConfig,
-    prefix: &str,
+    config: &SearchConfig<'_>,
+    prefix: &[u8],
     output: &mut Vec<u8>,
-) -> usize {
-    if pattern.supports_candidate_lines() {
+) -> Result<usize> {
+    if config.multiline {
+        search_multiline(input, pattern, config, prefix, output)
+    } else if pattern.supports_candidate_lines() {
         search_with_candidates(input, pattern, config, prefix, output)
     } else {
         search_line_by_line(input, pattern, config, prefix, output)
@@ -265,10 +502,11 @@ pub fn search_text_content(
 pub fn search_with_candidates(
     input: &str,
     pattern: &CompiledRegex,
-    config: &SearchConfig,
-    prefix: &str,
+    config: &SearchConfig<'_>,
+    prefix: &[u8],
     output: &mut Vec<u8>,
-) -> usize {
+) -> Result<usize> {
+    let budget = pattern.search_budget();
     let mut match_count = 0;
     let mut search_from = 0usize;
 
@@ -276,147 +514,405 @@ pub fn search_with_candidates(
         let position = match candidate {
             LineCandidate::Confirmed(pos) | LineCandidate::Candidate(pos) => pos,
         };
-        let (line_start, line_end) = line_bounds(input, position);
-        let line = strip_line_terminator(&input[line_start..line_end]);
-        let matches = find_all_regex_spans_compiled(line, pattern);
+        let (line_start, line_end) = line_bounds(input, position, config.line_terminator);
+        let line = strip_line_terminator(&input[line_start..line_end], config.line_terminator);
+        let matches = find_all_regex_spans_budgeted(line, pattern, &budget)?;
         if matches.is_empty() {
             search_from = line_end;
             continue;
         }
-        match_count += write_line_matches(output, line, prefix, config, &matches);
+        match_count +=
+            write_line_matches(output, line, prefix, pattern, config, &matches, &budget)?;
         search_from = line_end;
     }
 
-    match_count
+    Ok(match_count)
 }
 
 #[doc(hidden)]
 pub fn search_line_by_line(
     input: &str,
     pattern: &CompiledRegex,
-    config: &SearchConfig,
-    prefix: &str,
+    config: &SearchConfig<'_>,
+    prefix: &[u8],
     output: &mut Vec<u8>,
-) -> usize {
+) -> Result<usize> {
+    let budget = pattern.search_budget();
     let mut match_count = 0;
-    for line in input.lines() {
-        let matches = find_all_regex_spans_compiled(line, pattern);
+    for line in records(input, config.line_terminator) {
+        let matches = find_all_regex_spans_budgeted(line, pattern, &budget)?;
         if matches.is_empty() {
             continue;
         }
-        match_count += write_line_matches(output, line, prefix, config, &matches);
+        match_count +=
+            write_line_matches(output, line, prefix, pattern, config, &matches, &budget)?;
+    }
+    Ok(match_count)
+}
+
+/// Runs the pattern over the whole buffer so matches may span lines, then
+/// prints every line a match touches. Matches that share a line are printed
+/// as one block.
+#[doc(hidden)]
+pub fn search_multiline(
+    input: &str,
+    pattern: &CompiledRegex,
+    config: &SearchConfig<'_>,
+    prefix: &[u8],
+    output: &mut Vec<u8>,
+) -> Result<usize> {
+    // Without any lines there is nothing a match could be shown on.
+    if input.is_empty() {
+        return Ok(0);
+    }
+    let budget = pattern.search_budget();
+    let captures = match config.replacement {
+        Some(_) => find_all_regex_captures_budgeted(input, pattern, &budget)?,
+        None => find_all_regex_spans_budgeted(input, pattern, &budget)?
+            .into_iter()
+            .map(|matched| RegexCaptures::new(vec![Some(matched)]))
+            .collect(),
+    };
+
+    if config.only_matching {
+        let mut expanded = String::new();
+        for captured in &captures {
+            let whole = captured.whole();
+            output.extend_from_slice(prefix);
+            match config.replacement {
+                Some(replacement) => {
+                    expanded.clear();
+                    replacement.expand(input, captured, &mut expanded);
+                    output.extend_from_slice(expanded.as_bytes());
+                }
+                None => output.extend_from_slice(&input.as_bytes()[whole.start..whole.end]),
+            }
+            output.push(config.line_terminator);
+        }
+        return Ok(captures.len());
+    }
+
+    let mut line_count = 0;
+    let mut block: Option<(usize, usize, usize)> = None;
+    for (idx, captured) in captures.iter().enumerate() {
+        let whole = captured.whole();
+        // An empty match after the final terminator is on no line of its
+        // own, so it is shown on the last line.
+        let first_byte =
+            if whole.start == input.len() && input.ends_with(char::from(config.line_terminator)) {
+                whole.start - 1
+            } else {
+                whole.start
+            };
+        let last_byte = if whole.end > whole.start {
+            whole.end - 1
+        } else {
+            first_byte
+        };
+        let (match_start, _) = line_bounds(input, first_byte, config.line_terminator);
+        let (_, match_end) = line_bounds(input, last_byte, config.line_terminator);
+
+        block = match block {
+            Some((start, end, first)) if match_start < end => {
+                Some((start, end.max(match_end), first))
+            }
+            Some((start, end, first)) => {
+                line_count += write_multiline_block(
+                    output,
+                    input,
+                    (start, end),
+                    &captures[first..idx],
+                    prefix,
+                    config,
+                );
+                Some((match_start, match_end, idx))
+            }
+            None => Some((match_start, match_end, idx)),
+        };
+    }
+    if let Some((start, end, first)) = block {
+        line_count += write_multiline_block(
+            output,
+            input,
+            (start, end),
+            &captures[first..],
+            prefix,
+            config,
+        );
+    }
+
+    Ok(line_count)
+}
+
+fn write_multiline_block(
+    output: &mut Vec<u8>,
+    input: &str,
+    (block_start, block_end): (usize, usize),
+    captures: &[RegexCaptures],
+    prefix: &[u8],
+    config: &SearchConfig<'_>,
+) -> usize {
+    let mut text = String::new();
+    let mut spans = Vec::with_capacity(captures.len());
+    let mut last = block_start;
+    for captured in captures {
+        let whole = captured.whole();
+        text.push_str(&input[last..whole.start]);
+        let start = text.len();
+        match config.replacement {
+            Some(replacement) => replacement.expand(input, captured, &mut text),
+            None => text.push_str(&input[whole.start..whole.end]),
+        }
+        spans.push(RegexMatch {
+            start,
+            end: text.len(),
+        });
+        last = whole.end;
     }
-    match_count
+    text.push_str(strip_line_terminator(
+        &input[last.min(block_end)..block_end],
+        config.line_terminator,
+    ));
+
+    // A match that consumed the block's last terminator leaves it at the end
+    // of `text`, with no line after it.
+    let terminator = char::from(config.line_terminator);
+    let body = text.strip_suffix(terminator).unwrap_or(&text);
+
+    let highlight = config.use_color.then_some(config.colors.matched);
+    let mut line_start = 0;
+    let mut line_count = 0;
+    for line in body.split(terminator) {
+        let line_end = line_start + line.len();
+        let line_spans = spans
+            .iter()
+            .filter_map(|span| {
+                let start = span.start.max(line_start);
+                let end = span.end.min(line_end);
+                (start < end).then(|| RegexMatch {
+                    start: start - line_start,
+                    end: end - line_start,
+                })
+            })
+            .collect::<Vec<_>>();
+        let line = if config.line_terminator == b'\n' {
+            line.strip_suffix('\r').unwrap_or(line)
+        } else {
+            line
+        };
+        write_rendered_line(
+            output,
+            line,
+            prefix,
+            highlight,
+            config.line_terminator,
+            &line_spans,
+        )
+        .expect("writing to vec should not fail");
+        line_start = line_end + 1;
+        line_count += 1;
+    }
+    line_count
 }
 
 fn write_line_matches(
     output: &mut Vec<u8>,
     line: &str,
-    prefix: &str,
-    config: &SearchConfig,
+    prefix: &[u8],
+    pattern: &CompiledRegex,
+    config: &SearchConfig<'_>,
     matches: &[RegexMatch],
-) -> usize {
+    budget: &SearchBudget,
+) -> Result<usize> {
+    if let Some(replacement) = config.replacement {
+        return write_replaced_line(output, line, prefix, pattern, replacement, config, budget);
+    }
+
     if config.only_matching {
         for matched in matches {
-            output.extend_from_slice(prefix.as_bytes());
+            output.extend_from_slice(prefix);
             output.extend_from_slice(&line.as_bytes()[matched.start..matched.end]);
-            output.push(b'\n');
+            output.push(config.line_terminator);
         }
-        matches.len()
+        Ok(matches.len())
     } else {
-        write_rendered_line(output, line, prefix, config.use_color, matches)
-            .expect("writing to vec should not fail");
-        1
+        let highlight = config.use_color.then_some(config.colors.matched);
+        write_rendered_line(
+            output,
+            line,
+            prefix,
+            highlight,
+            config.line_terminator,
+            matches,
+        )
+        .expect("writing to vec should not fail");
+        Ok(1)
     }
 }
 
+fn write_replaced_line(
+    output: &mut Vec<u8>,
+    line: &str,
+    prefix: &[u8],
+    pattern: &CompiledRegex,
+    replacement: &Replacement,
+    config: &SearchConfig<'_>,
+    budget: &SearchBudget,
+) -> Result<usize> {
+    let captures = find_all_regex_captures_budgeted(line, pattern, budget)?;
+
+    if config.only_matching {
+        let mut expanded = String::new();
+        for captured in &captures {
+            expanded.clear();
+            replacement.expand(line, captured, &mut expanded);
+            output.extend_from_slice(prefix);
+            output.extend_from_slice(expanded.as_bytes());
+            output.push(config.line_terminator);
+        }
+        return Ok(captures.len());
+    }
+
+    let mut replaced = String::with_capacity(line.len());
+    let mut replaced_spans = Vec::with_capacity(captures.len());
+    let mut last = 0;
+    for captured in &captures {
+        let whole = captured.whole();
+        replaced.push_str(&line[last..whole.start]);
+        let start = replaced.len();
+        replacement.expand(line, captured, &mut replaced);
+        replaced_spans.push(RegexMatch {
+            start,
+            end: replaced.len(),
+        });
+        last = whole.end;
+    }
+    replaced.push_str(&line[last..]);
+
+    let highlight = config.use_color.then_some(config.colors.matched);
+    write_rendered_line(
+        output,
+        &replaced,
+        prefix,
+        highlight,
+        config.line_terminator,
+        &replaced_spans,
+    )
+    .expect("writing to vec should not fail");
+    Ok(1)
+}
+
 fn write_rendered_line<W: Write>(
     writer: &mut W,
     line: &str,
-    prefix: &str,
-    use_color: bool,
+    prefix: &[u8],
+    highlight: Option<Style>,
+    line_terminator: u8,
     matches: &[RegexMatch],
 ) -> io::Result<()> {
-    if matches.is_empty() || !use_color {
-        writer.write_all(prefix.as_bytes())?;
+    let Some(highlight) = highlight.filter(|_| !matches.is_empty()) else {
+        writer.write_all(prefix)?;
         writer.write_all(line.as_bytes())?;
-        writer.write_all(b"\n")?;
-        return Ok(());
-    }
+        return writer.write_all(&[line_terminator]);
+    };
 
-    writer.write_all(prefix.as_bytes())?;
+    writer.write_all(prefix)?;
 
     let mut last = 0;
     for matched in matches {
         writer.write_all(&line.as_bytes()[last..matched.start])?;
-        writer.write_all(ANSI_BOLD_RED)?;
-        writer.write_all(&line.as_bytes()[matched.start..matched.end])?;
-        writer.write_all(ANSI_RESET)?;
+        highlight.write_styled(writer, &line.as_bytes()[matched.start..matched.end])?;
         last = matched.end;
     }
     writer.write_all(&line.as_bytes()[last..])?;
-    writer.write_all(b"\n")
+    writer.write_all(&[line_terminator])
 }
 
-fn line_bounds(input: &str, position: usize) -> (usize, usize) {
-    let line_start = input[..position].rfind('\n').map_or(0, |idx| idx + 1);
+fn line_bounds(input: &str, position: usize, line_terminator: u8) -> (usize, usize) {
+    let terminator = char::from(line_terminator);
+    let line_start = input[..position].rfind(terminator).map_or(0, |idx| idx + 1);
     let line_end = input[position..]
-        .find('\n')
+        .find(terminator)
         .map_or(input.len(), |idx| position + idx + 1);
     (line_start, line_end)
 }
 
-fn strip_line_terminator(line: &str) -> &str {
-    line.strip_suffix('\n')
-        .and_then(|line| line.strip_suffix('\r').or(Some(line)))
-        .unwrap_or(line)
+pub(crate) fn strip_line_terminator(line: &str, line_terminator: u8) -> &str {
+    let Some(stripped) = line.strip_suffix(char::from(line_terminator)) else {
+        return line;
+    };
+    if line_terminator == b'\n' {
+        stripped.strip_suffix('\r').unwrap_or(stripped)
+    } else {
+        stripped
+    }
+}
+
+fn records(input: &str, line_terminator: u8) -> impl Iterator<Item = &str> {
+    input
+        .split_inclusive(char::from(line_terminator))
+        .map(move |record| strip_line_terminator(record, line_terminator))
 }
 
-#[doc(hidden)]
-pub fn collect_files(inputs: &[PathBuf], recursive: bool) -> Result<Vec<PathBuf>> {
-    let mut files = Vec::new();
+/// Expands `inputs` into search targets. With `archives`, tar and zip files
+/// become archive targets, which the searching worker reads.
+pub(crate) fn collect_files(
+    inputs: &[PathBuf],
+    recursive: bool,
+    archives: Option<ArchiveLimits>,
+) -> Result<Vec<SearchTarget>> {
+    let mut targets = Vec::new();
     for input in inputs {
         let meta = input.metadata()?;
         if meta.is_file() {
-            files.push(input.clone());
+            push_file(input.clone(), archives, &mut targets);
         } else if meta.is_dir() {
             if !recursive {
                 bail!("{}: Is a directory", input.to_string_lossy());
             }
-            files.extend(collect_dir(input)?);
+            collect_dir(input, archives, &mut targets)?;
         }
     }
-    Ok(files)
+    Ok(targets)
 }
 
-fn collect_dir(dir: &Path) -> Result<Vec<PathBuf>> {
-    let mut files = Vec::new();
+fn collect_dir(
+    dir: &Path,
+    archives: Option<ArchiveLimits>,
+    targets: &mut Vec<SearchTarget>,
+) -> Result<()> {
     let mut entries = dir.read_dir()?.collect::<std::io::Result<Vec<_>>>()?;
     entries.sort_by_key(|entry| entry.path());
     for entry in entries {
         let path = entry.path();
         let meta = entry.metadata()?;
         if meta.is_dir() {
-            files.extend(collect_dir(&path)?);
+            collect_dir(&path, archives, targets)?;
         } else if meta.is_file() {
-            files.push(path);
+            push_file(path, archives, targets);
         }
     }
-    Ok(files)
+    Ok(())
 }
 
-trait Pipe: Sized {
-    fn pipe<T>(self, f: impl FnOnce(Self) -> T) -> T {
-        f(self)
+fn push_file(path: PathBuf, archives: Option<ArchiveLimits>, targets: &mut Vec<SearchTarget>) {
+    match archives.filter(|&limits| is_archive(&path, limits)) {
+        Some(limits) => targets.push(SearchTarget::Archive(path, limits)),
+        None => targets.push(SearchTarget::File(path)),
     }
 }
 
-impl<T> Pipe for T {}
+fn archive_limits(config: &SearchConfig<'_>) -> Option<ArchiveLimits> {
+    config.search_archives.then_some(ArchiveLimits {
+        max_depth: config.max_archive_depth,
+        search_zip: config.search_zip,
+    })
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::compile_regex;
+    use crate::{compile_regex, compile_regex_with_options, BacktrackLimit, RegexOptions};
     use std::{
         fs,
         sync::atomic::{AtomicUsize, Ordering},
@@ -431,14 +927,36 @@ mod tests {
         dir.join(name)
     }
 
+    /// A config with every option off, which tests override with
+    /// `..base_config()`.
+    fn base_config() -> SearchConfig<'static> {
+        SearchConfig {
+            only_matching: false,
+            use_color: false,
+            colors: ColorSpecs::default(),
+            show_prefix: None,
+            null_after_name: false,
+            line_terminator: b'\n',
+            multiline: false,
+            replacement: None,
+            rewrite: None,
+            preprocessor: None,
+            search_zip: false,
+            search_archives: false,
+            max_archive_depth: 0,
+            threads: 1,
+        }
+    }
+
     #[test]
     fn writes_single_ansi_highlight() {
         let mut output = Vec::new();
         write_rendered_line(
             &mut output,
             "I have 3 apples",
-            "",
-            true,
+            b"",
+            Some(ColorSpecs::default().matched),
+            b'\n',
             &[RegexMatch { start: 7, end: 8 }],
         )
         .unwrap();
@@ -455,8 +973,9 @@ mod tests {
         write_rendered_line(
             &mut output,
             "a1b2c3",
-            "",
-            true,
+            b"",
+            Some(ColorSpecs::default().matched),
+            b'\n',
             &[
                 RegexMatch { start: 1, end: 2 },
                 RegexMatch { start: 3, end: 4 },
@@ -475,7 +994,7 @@ mod tests {
     fn recursive_search_requires_flag_for_directories() {
         let dir = temp_path("dir");
         fs::create_dir_all(&dir).unwrap();
-        let err = collect_files(&[dir], false).unwrap_err();
+        let err = collect_files(&[dir], false, None).unwrap_err();
         assert!(err.to_string().contains("Is a directory"));
     }
 
@@ -490,13 +1009,19 @@ mod tests {
         let compiled = compile_regex("foo");
         let config = SearchConfig {
             only_matching: true,
-            use_color: false,
-            show_prefix: true,
+            show_prefix: Some(true),
             threads: 2,
+            ..base_config()
         };
 
         let mut output = Vec::new();
-        let count = run_files_parallel(&mut output, files, &compiled, config).unwrap();
+        let count = run_files_parallel(
+            &mut output,
+            files.into_iter().map(SearchTarget::File).collect(),
+            &compiled,
+            config,
+        )
+        .unwrap();
 
         assert_eq!(count, 2);
         assert_eq!(
@@ -511,28 +1036,694 @@ mod tests {
 
     #[test]
     fn candidate_line_search_matches_line_by_line_for_literal_and_automata() {
-        let config = SearchConfig {
-            only_matching: false,
-            use_color: false,
-            show_prefix: false,
-            threads: 1,
-        };
-        let input = "ordinary line\nmessage=matched_line_42\nanother line\n";
+        let config = base_config();
+        let input = "ordinary line\nmessage=matched_line_42\nanother line\n\
+                     12 level=ERROR disk full\n7 level=WARN retry\nuser WARN=x Error 500\n";
 
         for regex in [
             "matched_line_",
             r"message=(matched_line|ordinary_line)_[0123456789]+",
+            r"[0-9]+ level=ERROR .*",
+            r"\w+(ERROR|WARN)=x",
+            r"[Ee]rror \d+$",
+            r"\d+ level=(?:ERROR|WARN)",
         ] {
             let compiled = compile_regex(regex);
+            assert!(compiled.supports_candidate_lines(), "pattern {regex}");
             let mut candidate_output = Vec::new();
             let mut line_output = Vec::new();
 
             let candidate_count =
-                search_with_candidates(input, &compiled, &config, "", &mut candidate_output);
-            let line_count = search_line_by_line(input, &compiled, &config, "", &mut line_output);
+                search_with_candidates(input, &compiled, &config, b"", &mut candidate_output)
+                    .unwrap();
+            let line_count =
+                search_line_by_line(input, &compiled, &config, b"", &mut line_output).unwrap();
 
             assert_eq!(candidate_count, line_count);
             assert_eq!(candidate_output, line_output);
         }
     }
+
+    #[test]
+    fn candidate_line_search_matches_line_by_line_for_backreferences() {
+        let config = base_config();
+        let input = "foo bar\nfoo foo\nid=12,12 id=3,4\n77x_ay_a77\nbar baz bar\n\
+                     été été\nlevel level\n";
+
+        for regex in [
+            r"(\w+) \1",
+            r"(\d+)x(\w+)y\2\1",
+            r"^(\w+) .* \1$",
+            r"(?<=id=)(\d+),\1",
+            r"\b(\w)\w*\1\b",
+            r"(?>(\w+)) \1",
+        ] {
+            let compiled = compile_regex(regex);
+            assert!(compiled.supports_candidate_lines(), "pattern {regex}");
+            let mut candidate_output = Vec::new();
+            let mut line_output = Vec::new();
+
+            let candidate_count =
+                search_with_candidates(input, &compiled, &config, b"", &mut candidate_output)
+                    .unwrap();
+            let line_count =
+                search_line_by_line(input, &compiled, &config, b"", &mut line_output).unwrap();
+
+            assert!(line_count > 0, "pattern {regex}");
+            assert_eq!(candidate_count, line_count, "pattern {regex}");
+            assert_eq!(candidate_output, line_output, "pattern {regex}");
+        }
+
+        // A self-reference has no approximation, but the literal still
+        // finds candidates.
+        assert!(compile_regex(r"(a\1b)x").supports_candidate_lines());
+        assert!(!compile_regex(r"(\w*)\1").supports_candidate_lines());
+    }
+
+    #[test]
+    fn null_after_name_replaces_prefix_separator() {
+        let file1 = temp_path("first.txt");
+        let file2 = temp_path("second.txt");
+        fs::write(&file1, "foo\n").unwrap();
+        fs::write(&file2, "bar\nfoo\n").unwrap();
+
+        let compiled = compile_regex("foo");
+        let config = SearchConfig {
+            null_after_name: true,
+            ..base_config()
+        };
+
+        let mut output = Vec::new();
+        let count = run_search_to_writer(
+            &mut output,
+            &[file1.clone(), file2.clone()],
+            false,
+            &compiled,
+            config,
+        )
+        .unwrap();
+
+        assert_eq!(count, 2);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "{}\0foo\n{}\0foo\n",
+                file1.to_string_lossy(),
+                file2.to_string_lossy()
+            )
+        );
+    }
+
+    #[test]
+    fn null_data_splits_records_on_nul() {
+        let config = SearchConfig {
+            line_terminator: b'\0',
+            ..base_config()
+        };
+        let input = "first\nfoo line\0second\0third foo\0";
+
+        for regex in ["foo", r"f\w+", r"(o)\1"] {
+            let compiled = compile_regex(regex);
+            let mut candidate_output = Vec::new();
+            let mut line_output = Vec::new();
+
+            let candidate_count =
+                search_with_candidates(input, &compiled, &config, b"", &mut candidate_output)
+                    .unwrap();
+            let line_count =
+                search_line_by_line(input, &compiled, &config, b"", &mut line_output).unwrap();
+
+            assert_eq!(line_count, 2);
+            assert_eq!(candidate_count, line_count);
+            assert_eq!(candidate_output, line_output);
+            assert_eq!(
+                String::from_utf8(line_output).unwrap(),
+                "first\nfoo line\0third foo\0"
+            );
+        }
+    }
+
+    #[test]
+    fn explicit_prefix_setting_overrides_file_count_heuristic() {
+        let dir = temp_path("tree");
+        fs::create_dir_all(&dir).unwrap();
+        let single = dir.join("only.txt");
+        fs::write(&single, "foo\n").unwrap();
+        let other = temp_path("other.txt");
+        fs::write(&other, "foo\n").unwrap();
+
+        let compiled = compile_regex("foo");
+        let config = SearchConfig {
+            only_matching: true,
+            show_prefix: Some(true),
+            ..base_config()
+        };
+
+        let mut output = Vec::new();
+        run_search_to_writer(&mut output, &[dir], true, &compiled, config).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!("{}:foo\n", single.to_string_lossy())
+        );
+
+        let mut output = Vec::new();
+        let config = SearchConfig {
+            show_prefix: Some(false),
+            ..config
+        };
+        run_search_to_writer(&mut output, &[single, other], false, &compiled, config).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "foo\nfoo\n");
+    }
+
+    #[test]
+    fn replacement_rewrites_full_lines_and_only_matching_output() {
+        let compiled = compile_regex(r"fn (\w+)");
+        let replacement = Replacement::parse("def $1", &compiled).unwrap();
+        let config = SearchConfig {
+            use_color: true,
+            replacement: Some(&replacement),
+            ..base_config()
+        };
+        let input = "fn main() { fn helper(); }\nlet x = 1;\n";
+
+        let mut output = Vec::new();
+        let count = search_text_content(input, &compiled, &config, b"", &mut output).unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\x1b[1;31mdef main\x1b[0m() { \x1b[1;31mdef helper\x1b[0m(); }\n"
+        );
+
+        let config = SearchConfig {
+            only_matching: true,
+            use_color: false,
+            ..config
+        };
+        let mut output = Vec::new();
+        let count = search_text_content(input, &compiled, &config, b"", &mut output).unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(String::from_utf8(output).unwrap(), "def main\ndef helper\n");
+    }
+
+    #[test]
+    fn in_place_rewrite_only_touches_matching_files() {
+        let changed = temp_path("changed.txt");
+        let untouched = temp_path("untouched.txt");
+        fs::write(&changed, "fn main() {}\nlet x = 1;\nfn helper() {}\n").unwrap();
+        fs::write(&untouched, "let y = 2;\n").unwrap();
+
+        let compiled = compile_regex(r"fn (\w+)");
+        let replacement = Replacement::parse("def $1", &compiled).unwrap();
+        let config = SearchConfig {
+            replacement: Some(&replacement),
+            rewrite: Some(RewriteConfig {
+                backup_suffix: Some(".orig"),
+                dry_run: false,
+                show_diff: false,
+            }),
+            threads: 2,
+            ..base_config()
+        };
+
+        let mut output = Vec::new();
+        let files = [changed.clone(), untouched.clone()];
+        let count = run_search_to_writer(&mut output, &files, false, &compiled, config).unwrap();
+
+        assert_eq!(count, 2);
+        assert!(output.is_empty());
+        assert_eq!(
+            fs::read_to_string(&changed).unwrap(),
+            "def main() {}\nlet x = 1;\ndef helper() {}\n"
+        );
+        assert_eq!(
+            fs::read_to_string(changed.with_extension("txt.orig")).unwrap(),
+            "fn main() {}\nlet x = 1;\nfn helper() {}\n"
+        );
+        assert_eq!(fs::read_to_string(&untouched).unwrap(), "let y = 2;\n");
+        assert!(!untouched.with_extension("txt.orig").exists());
+
+        let link = temp_path("link.txt");
+        std::os::unix::fs::symlink(&untouched, &link).unwrap();
+        let compiled = compile_regex(r"let (\w+)");
+        let replacement = Replacement::parse("const $1", &compiled).unwrap();
+        let config = SearchConfig {
+            replacement: Some(&replacement),
+            ..config
+        };
+        let count = run_search_to_writer(
+            &mut Vec::new(),
+            std::slice::from_ref(&link),
+            false,
+            &compiled,
+            config,
+        )
+        .unwrap();
+        assert_eq!(count, 1);
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(fs::read_to_string(&untouched).unwrap(), "const y = 2;\n");
+    }
+
+    #[test]
+    fn dry_run_diff_prints_unified_hunks_without_writing() {
+        let path = temp_path("diff.txt");
+        let original = "a\nfoo 1\nb\nc\nd\ne\nf\ng\nh\ni\nfoo 2";
+        fs::write(&path, original).unwrap();
+
+        let compiled = compile_regex(r"foo (\d)");
+        let replacement = Replacement::parse("bar $1", &compiled).unwrap();
+        let config = SearchConfig {
+            replacement: Some(&replacement),
+            rewrite: Some(RewriteConfig {
+                backup_suffix: None,
+                dry_run: true,
+                show_diff: true,
+            }),
+            ..base_config()
+        };
+
+        let mut output = Vec::new();
+        let files = [path.clone()];
+        let count = run_search_to_writer(&mut output, &files, false, &compiled, config).unwrap();
+
+        let name = path.to_string_lossy();
+        assert_eq!(count, 2);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "--- {name}\n+++ {name}\n\
+                 @@ -1,5 +1,5 @@\n a\n-foo 1\n+bar 1\n b\n c\n d\n\
+                 @@ -8,4 +8,4 @@\n g\n h\n i\n-foo 2\n\\ No newline at end of file\n\
+                 +bar 2\n\\ No newline at end of file\n"
+            )
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+    }
+
+    #[test]
+    fn multiline_search_prints_every_line_a_match_touches() {
+        let config = SearchConfig {
+            use_color: true,
+            multiline: true,
+            ..base_config()
+        };
+        let input = "start\nfoo bar\nbaz end\nother\n";
+        let options = RegexOptions {
+            multiline: true,
+            dot_matches_new_line: false,
+            differential: false,
+            ..RegexOptions::default()
+        };
+
+        for regex in [r"bar\nbaz", r"(ba)r\n\1z"] {
+            let compiled = compile_regex_with_options(regex, options);
+            let mut output = Vec::new();
+            let count = search_text_content(input, &compiled, &config, b"", &mut output).unwrap();
+
+            assert_eq!(count, 2);
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                "foo \x1b[1;31mbar\x1b[0m\n\x1b[1;31mbaz\x1b[0m end\n"
+            );
+        }
+    }
+
+    #[test]
+    fn multiline_matches_at_a_line_end_print_no_extra_line() {
+        let config = SearchConfig {
+            multiline: true,
+            ..base_config()
+        };
+        let options = RegexOptions {
+            multiline: true,
+            ..RegexOptions::default()
+        };
+
+        for (input, regex, expected) in [
+            ("ab\ncd\n", r"b\n", "ab\n"),
+            ("x\n", "$", "x\n"),
+            ("ab\n\ncd\n", r"b\n\n", "ab\n\n"),
+            ("", "$", ""),
+        ] {
+            let compiled = compile_regex_with_options(regex, options);
+            let mut output = Vec::new();
+            let count = search_text_content(input, &compiled, &config, b"", &mut output).unwrap();
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                expected,
+                "pattern {regex:?} on {input:?}"
+            );
+            assert_eq!(
+                count,
+                expected.lines().count(),
+                "pattern {regex:?} on {input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn multiline_dotall_lets_wildcards_cross_lines() {
+        let config = SearchConfig {
+            only_matching: true,
+            multiline: true,
+            ..base_config()
+        };
+        let input = "foo bar\nbaz end\n";
+
+        for (regex, expected) in [
+            (r"foo.*end", "foo bar\nbaz end\n"),
+            (r"(o)\1.+end", "oo bar\nbaz end\n"),
+        ] {
+            let dotall = compile_regex_with_options(
+                regex,
+                RegexOptions {
+                    multiline: true,
+                    dot_matches_new_line: true,
+                    differential: false,
+                    ..RegexOptions::default()
+                },
+            );
+            let mut output = Vec::new();
+            assert_eq!(
+                search_text_content(input, &dotall, &config, b"", &mut output).unwrap(),
+                1
+            );
+            assert_eq!(String::from_utf8(output).unwrap(), expected);
+
+            let plain = compile_regex_with_options(
+                regex,
+                RegexOptions {
+                    multiline: true,
+                    dot_matches_new_line: false,
+                    differential: false,
+                    ..RegexOptions::default()
+                },
+            );
+            let mut output = Vec::new();
+            assert_eq!(
+                search_text_content(input, &plain, &config, b"", &mut output).unwrap(),
+                0
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn preprocessor_output_is_searched_in_parallel_workers() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let script = temp_path("upper.sh");
+        fs::write(&script, "#!/bin/sh\ntr a-z A-Z < \"$1\"\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let encoded = temp_path("secret.enc");
+        let plain = temp_path("plain.txt");
+        fs::write(&encoded, "token here\n").unwrap();
+        fs::write(&plain, "token here\n").unwrap();
+
+        let preprocessor = Preprocessor::new(&script, &["*.enc".to_string()]).unwrap();
+        let compiled = compile_regex("(TOKEN|token)");
+        let config = SearchConfig {
+            only_matching: true,
+            show_prefix: Some(false),
+            preprocessor: Some(&preprocessor),
+            threads: 2,
+            ..base_config()
+        };
+
+        let mut output = Vec::new();
+        let files = [encoded.clone(), plain];
+        let count = run_search_to_writer(&mut output, &files, false, &compiled, config).unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(String::from_utf8(output).unwrap(), "TOKEN\ntoken\n");
+
+        let failing = Preprocessor::new("false", &["*.enc".to_string()]).unwrap();
+        let config = SearchConfig {
+            preprocessor: Some(&failing),
+            ..config
+        };
+        for threads in [1, 2] {
+            let mut output = Vec::new();
+            let count = run_search_to_writer(
+                &mut output,
+                &files,
+                false,
+                &compiled,
+                SearchConfig { threads, ..config },
+            )
+            .unwrap();
+            assert_eq!(count, 1);
+            assert_eq!(String::from_utf8(output).unwrap(), "token\n");
+        }
+
+        let err = search_file(
+            &SearchTarget::File(encoded.clone()),
+            &mut WorkerBuffers::default(),
+            &compiled,
+            &config,
+        )
+        .unwrap_err();
+        assert!(is_skippable(&err));
+        let message = format!("{err:#}");
+        assert!(
+            message.starts_with(&encoded.display().to_string()),
+            "{message}"
+        );
+        assert!(message.contains("preprocessor false failed"), "{message}");
+
+        let binary = temp_path("binary.sh");
+        fs::write(&binary, "#!/bin/sh\nprintf '\\377\\n'\n").unwrap();
+        fs::set_permissions(&binary, fs::Permissions::from_mode(0o755)).unwrap();
+        let missing = temp_path("missing-preprocessor");
+        for (command, expected) in [
+            (
+                &binary,
+                format!("preprocessor {} produced invalid UTF-8", binary.display()),
+            ),
+            (
+                &missing,
+                format!("failed to run preprocessor {}", missing.display()),
+            ),
+        ] {
+            let preprocessor = Preprocessor::new(command, &["*.enc".to_string()]).unwrap();
+            let config = SearchConfig {
+                preprocessor: Some(&preprocessor),
+                ..config
+            };
+            let mut output = Vec::new();
+            let count =
+                run_search_to_writer(&mut output, &files, false, &compiled, config).unwrap();
+            assert_eq!(count, 1);
+
+            let err = search_file(
+                &SearchTarget::File(encoded.clone()),
+                &mut WorkerBuffers::default(),
+                &compiled,
+                &config,
+            )
+            .unwrap_err();
+            assert!(is_skippable(&err));
+            let message = format!("{err:#}");
+            assert!(message.contains(&expected), "{message}");
+        }
+    }
+
+    #[test]
+    fn search_zip_reports_matches_under_the_compressed_file_name() {
+        // `printf 'first line\nsecond line\n' | gzip -9n`
+        let gzipped = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x4b, 0xcb, 0x2c, 0x2a,
+            0x2e, 0x51, 0xc8, 0xc9, 0xcc, 0x4b, 0xe5, 0x2a, 0x4e, 0x4d, 0xce, 0xcf, 0x4b, 0x81,
+            0xb0, 0x01, 0x2c, 0x5a, 0x45, 0x5d, 0x17, 0x00, 0x00, 0x00,
+        ];
+        let compressed = temp_path("rotated.log.gz");
+        let plain = temp_path("current.log");
+        fs::write(&compressed, gzipped).unwrap();
+        fs::write(&plain, "second try\n").unwrap();
+
+        let compiled = compile_regex("second");
+        let config = SearchConfig {
+            search_zip: true,
+            ..base_config()
+        };
+
+        let files = [compressed.clone(), plain.clone()];
+        let mut output = Vec::new();
+        let count = run_search_to_writer(&mut output, &files, false, &compiled, config).unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "{}:second line\n{}:second try\n",
+                compressed.display(),
+                plain.display()
+            )
+        );
+
+        let config = SearchConfig {
+            search_zip: false,
+            ..config
+        };
+        assert!(run_search_to_writer(&mut Vec::new(), &files, false, &compiled, config).is_err());
+    }
+
+    #[test]
+    fn files_that_do_not_decompress_are_searched_as_text_or_skipped() {
+        let dir = temp_path("not-compressed");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.txt"), "BZh is text\nneedle in bzh\n").unwrap();
+        fs::write(dir.join("b.gz"), b"\x1f\x8b\x08\xff garbage").unwrap();
+        fs::write(dir.join("c.txt"), "needle\n").unwrap();
+
+        let compiled = compile_regex("needle");
+        for threads in [1, 2] {
+            let config = SearchConfig {
+                search_zip: true,
+                threads,
+                ..base_config()
+            };
+            let mut output = Vec::new();
+            let count = run_search_to_writer(
+                &mut output,
+                std::slice::from_ref(&dir),
+                true,
+                &compiled,
+                config,
+            )
+            .unwrap();
+            assert_eq!(count, 2);
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                format!(
+                    "{}:needle in bzh\n{}:needle\n",
+                    dir.join("a.txt").display(),
+                    dir.join("c.txt").display()
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn recursive_search_reports_archive_members_and_skips_binaries() {
+        use crate::archive::fixtures::{build_tar, build_zip};
+
+        let dir = temp_path("release");
+        fs::create_dir_all(&dir).unwrap();
+        let nested = build_zip(&[("notes.txt", b"needle in zip\n")]);
+        let archive = build_tar(&[
+            ("inner/path.txt", b"needle in tar\n"),
+            ("bin/tool", b"needle\xff\xfe\n"),
+            ("inner/nested.zip", &nested),
+        ]);
+        fs::write(dir.join("artifact.tar"), archive).unwrap();
+        fs::write(dir.join("plain.txt"), "needle on disk\n").unwrap();
+
+        let compiled = compile_regex("needle");
+        let config = SearchConfig {
+            search_archives: true,
+            max_archive_depth: 2,
+            threads: 2,
+            ..base_config()
+        };
+
+        let mut output = Vec::new();
+        let count = run_search_to_writer(
+            &mut output,
+            std::slice::from_ref(&dir),
+            true,
+            &compiled,
+            config,
+        )
+        .unwrap();
+        let tar = dir.join("artifact.tar").display().to_string();
+        assert_eq!(count, 3);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "{tar}!inner/path.txt:needle in tar\n\
+                 {tar}!inner/nested.zip!notes.txt:needle in zip\n\
+                 {}:needle on disk\n",
+                dir.join("plain.txt").display()
+            )
+        );
+    }
+
+    #[test]
+    fn unreadable_archives_are_skipped_without_failing_the_search() {
+        let dir = temp_path("downloads");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.txt"), "needle\n").unwrap();
+        fs::write(dir.join("bad.zip"), b"PK\x03\x04garbage").unwrap();
+
+        let compiled = compile_regex("needle");
+        for threads in [1, 2] {
+            let config = SearchConfig {
+                search_archives: true,
+                max_archive_depth: 1,
+                threads,
+                ..base_config()
+            };
+            let mut output = Vec::new();
+            let count = run_search_to_writer(
+                &mut output,
+                std::slice::from_ref(&dir),
+                true,
+                &compiled,
+                config,
+            )
+            .unwrap();
+            assert_eq!(count, 1);
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                format!("{}:needle\n", dir.join("a.txt").display())
+            );
+        }
+
+        let err = read_archive(
+            &dir.join("bad.zip"),
+            ArchiveLimits {
+                max_depth: 1,
+                search_zip: false,
+            },
+        )
+        .unwrap_err();
+        assert!(is_skippable(&err));
+    }
+
+    #[test]
+    fn files_over_the_backtrack_limit_are_skipped_without_failing_the_search() {
+        let complex = temp_path("complex.txt");
+        let simple = complex.with_file_name("simple.txt");
+        // The runaway line ends in `cb` so that it gets past the prefilter.
+        let runaway = format!("{}cb", "a".repeat(40));
+        fs::write(&complex, format!("aaab\n{runaway}\n")).unwrap();
+        fs::write(&simple, "aaab\n").unwrap();
+
+        let compiled = compile_regex_with_options(
+            r"(\w)(a+)+\1b",
+            RegexOptions {
+                backtrack_limit: BacktrackLimit {
+                    steps_per_line: Some(10_000),
+                    ..BacktrackLimit::default()
+                },
+                ..RegexOptions::default()
+            },
+        );
+        for threads in [1, 2] {
+            let config = SearchConfig {
+                show_prefix: Some(false),
+                threads,
+                ..base_config()
+            };
+            let mut output = Vec::new();
+            let files = [complex.clone(), simple.clone()];
+            let count =
+                run_search_to_writer(&mut output, &files, false, &compiled, config).unwrap();
+            assert_eq!(count, 1);
+            assert_eq!(String::from_utf8(output).unwrap(), "aaab\n");
+        }
+
+        let err = search_text_content(&runaway, &compiled, &base_config(), b"", &mut Vec::new())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "pattern too complex for this input");
+    }
 }
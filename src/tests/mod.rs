@@ -620,3 +620,683 @@ fn backreference_matches_remain_leftmost_and_non_overlapping() {
         vec!["cat and cat".to_string(), "dog and dog".to_string()]
     );
 }
+
+#[test]
+fn captures_report_every_group_for_backreference_patterns() {
+    let compiled = compile_regex(r"((\w+)-(\d+)) and \1");
+    let input = "x token-42 and token-42";
+    let captures = find_all_regex_captures_compiled(input, &compiled);
+
+    assert_eq!(compiled.captures_len(), 4);
+    assert_eq!(captures.len(), 1);
+    let groups = (0..captures[0].len())
+        .map(|idx| {
+            captures[0]
+                .get(idx)
+                .map(|span| &input[span.start..span.end])
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        groups,
+        vec![
+            Some("token-42 and token-42"),
+            Some("token-42"),
+            Some("token"),
+            Some("42"),
+        ]
+    );
+}
+
+#[test]
+fn multiline_anchors_match_at_line_boundaries_in_every_engine() {
+    let options = RegexOptions {
+        multiline: true,
+        dot_matches_new_line: false,
+        differential: false,
+        ..RegexOptions::default()
+    };
+    let input = "cat\ndog cat\ncat cat\n";
+
+    for (regex, expected) in [
+        ("^cat", vec!["cat", "cat"]),
+        ("cat$", vec!["cat", "cat", "cat"]),
+        (r"^(\w+) \1$", vec!["cat cat"]),
+        (r"^\w+ (cat)$", vec!["dog cat", "cat cat"]),
+    ] {
+        let compiled = compile_regex_with_options(regex, options);
+        let found = find_all_regex_spans_compiled(input, &compiled)
+            .into_iter()
+            .map(|matched| &input[matched.start..matched.end])
+            .collect::<Vec<_>>();
+        assert_eq!(found, expected, "pattern {regex}");
+    }
+}
+
+#[test]
+fn backreference_character_groups_support_full_class_syntax() {
+    for (input, regex, expected) in [
+        ("key_1=key_1 x=y", r"([a-z0-9_]+)=\1", vec!["key_1=key_1"]),
+        ("a-x- b.x. c-x.", r"([.-])x\1", vec!["-x-", ".x."]),
+        ("aa  bb", r"([^abc])\1", vec!["  "]),
+        ("[]-[] ]]", r"([\]\[]+)-\1", vec!["[]-[]"]),
+        ("go go 42 42", r"([[:alpha:]]+) \1", vec!["go go"]),
+        ("go go 42 42", r"([[:^alpha:][:space:]]+)\1", vec![" 42 42"]),
+        ("éü-éü ab-ab", r"([é-ü]+)-\1", vec!["éü-éü"]),
+        ("héllo héllo 12 12", r"(\p{L}+) \1", vec!["héllo héllo"]),
+        ("a)|)| b", r"([)|]+)\1", vec![")|)|"]),
+        ("x\ty x\ty", r"(x[\t]y) \1", vec!["x\ty x\ty"]),
+        ("ü-xü üü-xüü", r"(.)-x\1", vec!["ü-xü", "ü-xü"]),
+        ("éa-xa", r"é(a)-x\1", vec!["éa-xa"]),
+    ] {
+        assert_eq!(find_all_regex(input, regex), expected, "pattern {regex}");
+    }
+}
+
+#[test]
+fn backreference_vm_agrees_with_automata_engine() {
+    let inputs = [
+        "",
+        "abc",
+        "cat and cat, dog and dog",
+        "token-42 x_1=y_2 (a|b) [x] \\ $5.00",
+        "naïve café über Straße 東京 ١٢٣ 42",
+        "tab\there\u{a0}nbsp\u{2003}em",
+        "aaa bbb\naaa\n\nccc",
+        "<a>x</a> <b>(ab)(c)</b> ababc",
+    ];
+    let patterns = [
+        r"\w+",
+        r"\d+",
+        r"\w\d",
+        r"[\w]+",
+        r"[^\w\s]+",
+        r"[\d.]+",
+        r"[\s]+",
+        r"\p{L}+",
+        r"\P{L}+",
+        r"[^a-z]",
+        r"(\w+) and (\w+)",
+        r"(\w)+-(\d)?",
+        r"(c.t|d.g)",
+        r"a*",
+        r"b?",
+        r"x*y*",
+        r"(a|ab)(c|bcd)?",
+        r"\w{2,3}",
+        r"[[:alpha:]]+",
+        r"a.c",
+        r".+",
+        r"<a>x</a> <b>",
+        r"\(a\|b\)",
+        r"\[x\]",
+        r"\$\d+\.\d+",
+        r"\\",
+        r"^\w+",
+        r"\w+$",
+        r"^aaa$",
+        r"[\]\[]",
+        r"^\w*",
+        r"^\w*$",
+        r"a.*\n",
+        r"\s*",
+        r"\bcat\b",
+        r"\B\w+",
+        r"\w+\b",
+        r"\bé\w*",
+        r"\D+",
+        r"\W+",
+        r"\S+",
+        r"\s\S",
+        r"[\s\d]+",
+        r"\x41|\x{e9}",
+        r"\u00e9\U0001F600?",
+        r"\t\w+",
+        r"[\x41-\x5a\t]+",
+        r"\(\w+\|\w\)",
+        r"\.\d\d",
+        r"(?:a|ab)(?:c|bcd)?",
+        r"(?P<word>\w+) (?<num>\d+)?",
+        r"(?:\w(\w))+",
+        r"a+?",
+        r"\w*?a",
+        r"\w*a",
+        r"\d?x",
+        r"<.+?>|\(.*?\)",
+        r"(\w{2,4}?)\w",
+        r"a??b?",
+        r"(?:ab)*?c",
+        r"x(^|,)y",
+        r"(^a|b$)",
+        r"(?:^|\s)\w",
+        r"\w(?:$|,)",
+        r"(^\w+$)",
+        r"a$|^b",
+        r"(?:a\n^)+",
+        r"$\n?^",
+    ];
+
+    for (multiline, dot_matches_new_line) in [(false, false), (true, false), (true, true)] {
+        let options = RegexOptions {
+            multiline,
+            dot_matches_new_line,
+            differential: true,
+            ..RegexOptions::default()
+        };
+        for pattern in patterns {
+            let compiled = compile_regex_with_options(pattern, options);
+            for input in inputs {
+                let _ = find_all_regex_spans_compiled(input, &compiled);
+                let _ = find_all_regex_captures_compiled(input, &compiled);
+            }
+        }
+    }
+}
+
+#[test]
+fn backreference_patterns_follow_automata_semantics() {
+    for (input, regex, expected) in [
+        (
+            "café café über über",
+            r"(\w+) \1",
+            vec!["café café", "über über"],
+        ),
+        ("٣٣ 12", r"(\d)\1", vec!["٣٣"]),
+        (
+            "(a(a [x[x |y|y",
+            r"(.)(.)\1\2",
+            vec!["(a(a", "[x[x", "|y|y"],
+        ),
+        ("a\\a", r"(.)\\\1", vec!["a\\a"]),
+        ("x$x$", r"(x)\$\1\$", vec!["x$x$"]),
+        ("ab-b", r"(\w)+-(\d)?\1", vec!["ab-b"]),
+    ] {
+        assert_eq!(find_all_regex(input, regex), expected, "pattern {regex}");
+    }
+}
+
+#[test]
+fn backreference_patterns_support_every_escape() {
+    for (input, regex, expected) in [
+        ("cat cat concat cat", r"\b(\w+) \1\b", vec!["cat cat"]),
+        ("xcat cat", r"\B(\w+) \1", vec!["cat cat"]),
+        ("12-ab-ab 1-1", r"(\D+)-\1", vec!["ab-ab"]),
+        ("a  b\t\tc", r"(\s)\1", vec!["  ", "\t\t"]),
+        ("x..x ab", r"(\W+)x\1", vec![]),
+        ("é=é A=A", r"(\u{e9}|\x41)=\1", vec!["é=é", "A=A"]),
+        ("a.b.c a.b", r"(\w\.)\1", vec![]),
+        ("(x)(x)", r"(\(x\))\1", vec!["(x)(x)"]),
+        ("a\tb a\tb", r"(a\tb) \1", vec!["a\tb a\tb"]),
+        ("😀😀", r"([\U0001F600-\U0001F64F])\1", vec!["😀😀"]),
+    ] {
+        assert_eq!(find_all_regex(input, regex), expected, "pattern {regex}");
+    }
+}
+
+#[test]
+fn backreference_groups_can_be_non_capturing_or_named() {
+    for (input, regex, expected) in [
+        ("ab-b ac-c", r"(?:a)(\w)-\1", vec!["ab-b", "ac-c"]),
+        ("xy-y zz-z", r"(?:x|z)(y|z)-\1", vec!["xy-y", "zz-z"]),
+        (
+            "cat=cat dog=cow",
+            r"(?P<word>\w+)=\k<word>",
+            vec!["cat=cat"],
+        ),
+        (
+            "1-2 1-2 1-3",
+            r"(?<a>\d)-(?<b>\d) \k<a>-\k<b>",
+            vec!["1-2 1-2"],
+        ),
+        (
+            "aa:aa ab:ab",
+            r"(?:(a)(?:\w))(?::\1)+",
+            vec!["aa:a", "ab:a"],
+        ),
+    ] {
+        assert_eq!(find_all_regex(input, regex), expected, "pattern {regex}");
+    }
+
+    let compiled = compile_regex(r"(?:key )(?<key>\w+)=(?P<value>\w+) \k<key>");
+    assert_eq!(compiled.captures_len(), 3);
+    assert_eq!(compiled.capture_index("key"), Some(1));
+    assert_eq!(compiled.capture_index("value"), Some(2));
+    assert_eq!(compiled.capture_index("missing"), None);
+    let captures = find_all_regex_captures_compiled("key a=1 a", &compiled);
+    assert_eq!(captures[0].get(2), Some(RegexMatch { start: 6, end: 7 }));
+}
+
+#[test]
+fn lazy_quantifiers_prefer_the_fewest_repetitions() {
+    for (input, regex, expected) in [
+        (
+            "<b>x</b> y </b><i>z</i>",
+            r"<(\w+)>.*?</\1>",
+            vec!["<b>x</b>", "<i>z</i>"],
+        ),
+        (
+            "<b>x</b> y </b>",
+            r"<(\w+)>.*</\1>",
+            vec!["<b>x</b> y </b>"],
+        ),
+        ("aaaa", r"(a+?)\1", vec!["aa", "aa"]),
+        ("aaaa", r"(a+)\1", vec!["aaaa"]),
+        ("1234-12", r"(\d{2,4}?)\d*-\1", vec!["1234-12"]),
+        ("xyx y", r"(x??)y\1", vec!["xyx", "y"]),
+        ("abab-ab", r"((?:ab)+?)\w*-\1", vec!["abab-ab"]),
+        ("ac abc", r"ab*?c", vec!["ac", "abc"]),
+    ] {
+        assert_eq!(find_all_regex(input, regex), expected, "pattern {regex}");
+    }
+}
+
+#[test]
+fn required_literals_never_skip_a_matching_line() {
+    for (regex, line) in [
+        ("x(?i)abc", "xABC"),
+        ("(?i:error) code", "ERROR code"),
+        ("colou?r", "color"),
+        ("(foo|)bar", "bar"),
+        ("a{0,2}bc", "bc"),
+        ("[Ee]rror", "error"),
+        ("(?:ab){2}c", "ababc"),
+        ("x|yz", "x"),
+        (r"\d+ level=ERROR", "12 level=ERROR"),
+        (r"(?:warn|error)=\d+ (?:x|yy)", "error=4 x"),
+    ] {
+        let compiled = compile_regex(regex);
+        assert!(
+            !find_all_regex(line, regex).is_empty(),
+            "pattern {regex} should match {line:?}"
+        );
+        assert!(
+            compiled.find_candidate_line(line, 0).is_some() || !compiled.supports_candidate_lines(),
+            "pattern {regex}"
+        );
+    }
+}
+
+#[test]
+fn optional_characters_are_not_required_by_candidate_lines() {
+    for regex in ["ab*c", "ab?c", "ab*?c", "ab{0,2}c"] {
+        let compiled = compile_regex(regex);
+        assert!(
+            compiled.find_candidate_line("ac", 0).is_some() || !compiled.supports_candidate_lines(),
+            "pattern {regex}"
+        );
+    }
+}
+
+#[test]
+fn atomic_groups_and_possessive_quantifiers_do_not_backtrack() {
+    for (input, regex, expected) in [
+        ("aaab", r"(?>a+)b", vec!["aaab"]),
+        ("aaaa", r"(?>a+)a", vec![]),
+        ("aaaa", r"a++a", vec![]),
+        ("aab b", r"a*+b", vec!["aab", "b"]),
+        ("ab", r"a?+ab", vec![]),
+        ("aaa", r"a{1,2}+a", vec!["aaa"]),
+        ("abc ac", r"(?>ab|a)c", vec!["abc", "ac"]),
+        ("abc ac", r"(?>a|ab)c", vec!["ac"]),
+        (r#""x" "y""#, r#""[^"]*+""#, vec![r#""x""#, r#""y""#]),
+        ("abab-ab", r"(?>(ab)+)-\1", vec!["abab-ab"]),
+        ("aaaa", r"(?>(a+))\1", vec![]),
+        ("aaab", r"(\w)(?>(?:\1)+)b", vec!["aaab"]),
+        ("aab", r"(?>a)++b", vec!["aab"]),
+        ("a+b", r"a\++b", vec!["a+b"]),
+        ("a+b", r"a[+]+b", vec!["a+b"]),
+    ] {
+        assert_eq!(find_all_regex(input, regex), expected, "pattern {regex}");
+    }
+}
+
+#[test]
+fn atomic_groups_cut_off_near_miss_backtracking() {
+    let input = format!("{}c", "a".repeat(64));
+    assert!(find_all_regex(&input, r"(?>a+)+b").is_empty());
+    assert!(find_all_regex(&input, r"a++a++a++a++b").is_empty());
+}
+
+#[test]
+fn lookaround_assertions_do_not_consume_input() {
+    for (input, regex, expected) in [
+        ("foobar foobaz", r"foo(?=bar)", vec!["foo"]),
+        ("foobar foobaz", r"foo(?!bar)", vec!["foo"]),
+        ("$10 €20 30", r"(?<=\$)\d+", vec!["10"]),
+        ("$10 €20 30", r"(?<!\$)\b\d+", vec!["20", "30"]),
+        ("é1 a2 3", r"(?<=\w)\d", vec!["1", "2"]),
+        ("ab-ab ab-cd", r"(?=(\w+)-\1)\w+", vec!["ab"]),
+        ("abcabc", r"(?=(abc))\1\1", vec!["abcabc"]),
+        ("x-y", r"(?<=x|y)-", vec!["-"]),
+        ("aaa", r"(?=a)", vec!["", "", ""]),
+        ("a1 b2", r"(?<=(?<![a])b)\d", vec!["2"]),
+        ("password1 password", r"\b(?=\w*\d)\w+", vec!["password1"]),
+    ] {
+        assert_eq!(find_all_regex(input, regex), expected, "pattern {regex}");
+    }
+}
+
+#[test]
+#[should_panic(expected = "lookbehind requires a fixed-width body")]
+fn lookbehind_requires_a_fixed_width_body() {
+    let _ = compile_regex(r"(?<=a+)b");
+}
+
+#[test]
+fn backreferences_can_be_multi_digit_relative_or_braced() {
+    let ten_groups = "(a)(b)(c)(d)(e)(f)(g)(h)(i)(j)";
+    for (input, regex, expected) in [
+        (
+            "abcdefghijj abcdefghija0",
+            format!(r"{ten_groups}\10"),
+            vec!["abcdefghijj"],
+        ),
+        (
+            "abcdefghija0 abcdefghijj",
+            format!(r"{ten_groups}\g{{1}}0"),
+            vec!["abcdefghija0"],
+        ),
+        ("ab-b ab-a", r"(a)(b)-\g{-1}".to_string(), vec!["ab-b"]),
+        ("ab-b ab-a", r"(a)(b)-\g{-2}".to_string(), vec!["ab-a"]),
+        ("xx-x", r"(?<c>x)\g{c}-\g{1}".to_string(), vec!["xx-x"]),
+        ("aa-a", r"((a)\g{-1})-\2".to_string(), vec!["aa-a"]),
+    ] {
+        assert_eq!(find_all_regex(input, &regex), expected, "pattern {regex}");
+    }
+}
+
+#[test]
+fn backreferences_to_missing_groups_fail_to_compile() {
+    for (regex, expected) in [
+        (
+            r"(a)\2",
+            r"backreference \2 refers to a missing capture group",
+        ),
+        (
+            r"(a)\12",
+            r"backreference \12 refers to a missing capture group",
+        ),
+        (r"(?<=a+)(x)\1", "lookbehind requires a fixed-width body"),
+    ] {
+        let err = try_compile_regex_with_options(regex, RegexOptions::default())
+            .err()
+            .unwrap_or_else(|| panic!("pattern {regex} compiled"));
+        assert_eq!(format!("{err:#}"), expected, "pattern {regex}");
+    }
+}
+
+#[test]
+#[should_panic(expected = "relative backreference \\g{-2} refers to a missing capture group")]
+fn relative_backreferences_to_missing_groups_fail_to_compile() {
+    let _ = compile_regex(r"(a)\g{-2}");
+}
+
+#[test]
+fn anchors_inside_groups_are_assertions_in_backreference_patterns() {
+    for (input, regex, expected) in [
+        ("aa ab bb", r"(^a|b$)\1", vec!["aa"]),
+        ("ab bb", r"(^a|b$)\1", vec![]),
+        ("x x,y ,x,y", r"(^|,)x\1", vec!["x", ",x,"]),
+        ("ab ab", r"(\w+)(?: |$)\1$", vec!["ab ab"]),
+        ("a$a", r"(a)\$\1", vec!["a$a"]),
+        ("ab-ab", r"^(?:(\w+)-\1)$", vec!["ab-ab"]),
+        ("ab-ab x", r"^(?:(\w+)-\1)$", vec![]),
+    ] {
+        assert_eq!(find_all_regex(input, regex), expected, "pattern {regex}");
+    }
+
+    let options = RegexOptions {
+        multiline: true,
+        dot_matches_new_line: false,
+        differential: false,
+        ..RegexOptions::default()
+    };
+    let compiled = compile_regex_with_options(r"(\w)(?:$\n^\1)+", options);
+    let spans = find_all_regex_spans_compiled("a\na\na\nb\nb", &compiled);
+    assert_eq!(
+        spans,
+        vec![
+            RegexMatch { start: 0, end: 5 },
+            RegexMatch { start: 6, end: 9 },
+        ]
+    );
+}
+
+#[test]
+fn one_parser_handles_literals_prefixes_and_backreferences() {
+    for (input, regex, expected) in [
+        ("TOKEN token", r"TOKEN|token", vec!["TOKEN", "token"]),
+        ("a$b ab", r"a$b", vec![]),
+        ("a} b", r"a}", vec!["a}"]),
+        ("aaa ab", r"(a)\1+", vec!["aaa"]),
+        ("xx yy xy", r"(x)\1|(y)\2", vec!["xx", "yy"]),
+    ] {
+        assert_eq!(find_all_regex(input, regex), expected, "pattern {regex}");
+    }
+
+    let compiled = compile_regex(r"TOKEN\d+|token\d+");
+    assert!(
+        compiled.find_candidate_line("token1", 0).is_some() || !compiled.supports_candidate_lines()
+    );
+}
+
+#[test]
+fn syntax_errors_report_the_offending_span() {
+    for (regex, expected) in [
+        ("a(b", "unclosed group at 1..3"),
+        (r"(a)\1(b", "unclosed group at 5..7"),
+    ] {
+        let err = try_compile_regex_with_options(regex, RegexOptions::default())
+            .err()
+            .unwrap_or_else(|| panic!("pattern {regex} compiled"));
+        assert_eq!(format!("{err:#}"), expected, "pattern {regex}");
+    }
+}
+
+#[test]
+fn backreferences_see_empty_iterations_and_zero_repetitions() {
+    for (input, regex, expected) in [
+        ("é", r"(a{0,3})*é\1", vec!["é"]),
+        ("aaéaa", r"(a{0,3})*é\1", vec!["aaéaa"]),
+        ("ab", r"(a)(c){0}b\1", vec![]),
+        ("aba", r"(a)(c){0}b\1", vec!["aba"]),
+        ("ab", r"(a)(c){0}(b)\2", vec![]),
+    ] {
+        assert_eq!(find_all_regex(input, regex), expected, "pattern {regex}");
+    }
+}
+
+#[test]
+fn word_start_and_end_assertions_are_not_literals() {
+    for (input, regex, expected) in [
+        ("a foo", r"\<fo+", vec!["foo"]),
+        ("a foo", r"\bfo+", vec!["foo"]),
+        ("afoo", r"\<fo+", vec![]),
+        ("foo a", r"fo+\>", vec!["foo"]),
+        ("fooa", r"fo+\>", vec![]),
+        ("<foo>", r"\<foo\>", vec!["foo"]),
+    ] {
+        assert_eq!(find_all_regex(input, regex), expected, "pattern {regex}");
+    }
+}
+
+#[test]
+fn word_start_and_end_assertions_are_rejected_with_backreferences() {
+    for (regex, expected) in [
+        (
+            r"\<(\w+) \1",
+            r"unsupported word boundary assertion: \< at 0..2",
+        ),
+        (
+            r"(\w+) \1\>",
+            r"unsupported word boundary assertion: \> at 8..10",
+        ),
+    ] {
+        let err = try_compile_regex_with_options(regex, RegexOptions::default())
+            .err()
+            .unwrap_or_else(|| panic!("pattern {regex} compiled"));
+        assert_eq!(format!("{err:#}"), expected, "pattern {regex}");
+    }
+}
+
+#[test]
+#[should_panic(expected = "group flags (?i) are not supported with backreferences")]
+fn flag_groups_are_rejected_with_backreferences() {
+    let _ = compile_regex(r"(?i)(a)\1");
+}
+
+#[test]
+fn backtracking_stops_when_the_step_budget_runs_out() {
+    let limited = |backtrack_limit| {
+        compile_regex_with_options(
+            r"(\w)(a+)+\1b",
+            RegexOptions {
+                backtrack_limit,
+                ..RegexOptions::default()
+            },
+        )
+    };
+    let expected = Ok(vec![RegexMatch { start: 0, end: 4 }]);
+    let runaway = "a".repeat(40);
+
+    let per_line = limited(BacktrackLimit {
+        steps_per_line: Some(10_000),
+        ..BacktrackLimit::default()
+    });
+    let budget = per_line.search_budget();
+    assert_eq!(
+        find_all_regex_spans_budgeted("aaab", &per_line, &budget),
+        expected
+    );
+    assert_eq!(
+        find_all_regex_spans_budgeted(&runaway, &per_line, &budget),
+        Err(BacktrackLimitExceeded)
+    );
+    assert_eq!(
+        find_all_regex_captures_budgeted("aaab", &per_line, &budget).map(|found| found.len()),
+        Err(BacktrackLimitExceeded),
+        "a budget that ran out stays spent for the rest of the file"
+    );
+
+    let per_file = limited(BacktrackLimit {
+        steps_per_file: Some(100),
+        ..BacktrackLimit::default()
+    });
+    let budget = per_file.search_budget();
+    let results = (0..50)
+        .map(|_| find_all_regex_spans_budgeted("aaab", &per_file, &budget))
+        .collect::<Vec<_>>();
+    assert_eq!(results.first(), Some(&expected));
+    assert_eq!(results.last(), Some(&Err(BacktrackLimitExceeded)));
+
+    let timed = limited(BacktrackLimit {
+        timeout: Some(std::time::Duration::ZERO),
+        ..BacktrackLimit::default()
+    });
+    assert_eq!(
+        find_all_regex_spans_budgeted(&runaway, &timed, &timed.search_budget()),
+        Err(BacktrackLimitExceeded)
+    );
+}
+
+#[test]
+fn memoized_backtracking_keeps_nested_quantifiers_polynomial() {
+    let options = RegexOptions {
+        backtrack_limit: BacktrackLimit {
+            steps_per_line: Some(100_000),
+            ..BacktrackLimit::default()
+        },
+        ..RegexOptions::default()
+    };
+    let runaway = "a".repeat(40);
+    for (regex, input) in [
+        (r"(a+)+\1b", runaway.clone()),
+        (r"(?=a)(?:a|aa)+b", runaway.clone()),
+        (r"(?>x?)(a*)*b", runaway.clone()),
+        (r"(\w)\1 (?:a+)+b", format!("xx {runaway}")),
+    ] {
+        let compiled = compile_regex_with_options(regex, options);
+        assert_eq!(
+            find_all_regex_spans_budgeted(&input, &compiled, &compiled.search_budget()),
+            Ok(vec![]),
+            "pattern {regex}"
+        );
+    }
+
+    for (input, regex, expected) in [
+        ("aaab", r"(a+)+\1b", vec!["aaab"]),
+        ("ababc abc", r"(?=a)(a|ab)+c", vec!["ababc", "abc"]),
+        ("xx aab", r"(\w)\1 (?:a+)+b", vec!["xx aab"]),
+    ] {
+        assert_eq!(find_all_regex(input, regex), expected, "pattern {regex}");
+    }
+}
+
+#[test]
+fn memoization_agrees_with_unmemoized_runs_across_empty_iterations() {
+    // Lines this long run without memoization, and a blank line before the
+    // real one matches none of these patterns.
+    let padding = "\n".repeat(1 << 18);
+    for (regex, input) in [
+        (r"(\w?)+=\1", "k=v"),
+        (r"(.?)+x\1", "ax"),
+        (r"(ac|.*)+\1$", "acac"),
+        (r"(ac|.*)+\1$", "xacac"),
+    ] {
+        let memoized = find_all_regex_spans(input, regex);
+        assert!(!memoized.is_empty(), "pattern {regex} on {input:?}");
+        let unmemoized = find_all_regex_spans(&format!("{padding}{input}"), regex)
+            .into_iter()
+            .map(|matched| RegexMatch {
+                start: matched.start - padding.len(),
+                end: matched.end - padding.len(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(memoized, unmemoized, "pattern {regex} on {input:?}");
+    }
+}
+
+#[test]
+fn fused_loops_give_back_characters_when_backtracking() {
+    for (input, regex, expected) in [
+        ("x aaab x", r"(x) a*ab \1", vec!["x aaab x"]),
+        ("k ab:kx k", r"(k) .*x \1", vec!["k ab:kx k"]),
+        ("12345 1", r"(\d)\d{2,3}\d+ \1", vec!["12345 1"]),
+        ("éééx éé", r"(?=é)é+(x) é+", vec!["éééx éé"]),
+        (
+            "the cat sat cat",
+            r"(\w+) (?:\w+ )*\1$",
+            vec!["cat sat cat"],
+        ),
+        ("ab-ab ab", r"(ab)-\1 ab", vec!["ab-ab ab"]),
+        ("zz a1 b", r"(?<=zz )([a-z]?)\d*\s", vec!["a1 "]),
+    ] {
+        assert_eq!(find_all_regex(input, regex), expected, "pattern {regex}");
+    }
+}
+
+#[test]
+fn segment_sequences_match_like_the_vm() {
+    for (input, regex, expected) in [
+        (
+            "4821xorder_99yorder_994821",
+            r"(\d+)x(\w+)y\2\1",
+            vec!["4821xorder_99yorder_994821"],
+        ),
+        (
+            "12x3y312 12x3y321",
+            r"(\d+)x(\w+)y\2\1",
+            vec!["12x3y312", "2x3y32"],
+        ),
+        ("ab:ab:abab", r"(\w+):\1:(\1+)", vec!["ab:ab:abab"]),
+        ("k=v k=v", r"((\w)=(\w)) \2=\3", vec!["k=v k=v"]),
+        ("aaaa", r"(a+?)\1", vec!["aa", "aa"]),
+        ("xyz xyz!", r"(\w{2,})(\w*) \1\2[!?]", vec!["xyz xyz!"]),
+        ("été été", r"(\w+) \1", vec!["été été"]),
+        ("a-b a-b", r"(.)-(.) \1.\2", vec!["a-b a-b"]),
+        ("aaaa", r"(a)a*?\1$", vec!["aaaa"]),
+        ("ab ab ab", r"(\w+) \1$", vec!["ab ab"]),
+        ("aa", r"(a\1)+", vec!["aa"]),
+    ] {
+        assert_eq!(find_all_regex(input, regex), expected, "pattern {regex}");
+    }
+}